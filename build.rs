@@ -0,0 +1,46 @@
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Expose the git commit, build date, rustc version and target to `keepc --version`
+fn main() {
+    let git_hash = run("git", &["rev-parse", "--short", "HEAD"]).unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=KEEPC_GIT_HASH={}", git_hash);
+    println!("cargo:rustc-env=KEEPC_BUILD_DATE={}", build_date());
+
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version = run(&rustc, &["--version"]).unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=KEEPC_RUSTC_VERSION={}", rustc_version);
+    println!("cargo:rustc-env=KEEPC_TARGET={}", std::env::var("TARGET").unwrap_or_default());
+
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+}
+
+// Run a program and return its trimmed output if it succeeded
+fn run(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// Today's date as YYYY-MM-DD, or the date of $SOURCE_DATE_EPOCH for reproducible builds
+fn build_date() -> String {
+    let seconds = std::env::var("SOURCE_DATE_EPOCH").ok()
+    .and_then(|epoch| epoch.parse::<u64>().ok())
+    .unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or_default());
+
+    // Convert days since 1970-01-01 to a civil date
+    let days = (seconds / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
@@ -0,0 +1,231 @@
+// Full-screen browser of `keepc browse`, built with `--features tui`
+
+use anyhow::{Context, Result};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::path::Path;
+
+use crate::{CommandStore, Config, RunArgs, SearchOptions, SortOrder};
+use crate::{copy_to_clipboard, listed_commands, mask_secrets, run_selected, search_logic};
+
+// What the keys currently do
+enum Mode {
+    // Keys act on the highlighted command
+    Normal,
+    // Keys edit the search box
+    Search,
+    // Keys edit the description of the highlighted command
+    Describe(String),
+    // Waiting for `y` to delete the highlighted command
+    ConfirmDelete,
+}
+
+struct Browser {
+    store: CommandStore,
+    sort: SortOrder,
+    query: String,
+    matches: Vec<String>,
+    state: ListState,
+    mode: Mode,
+    // Result of the last action, shown instead of the key help
+    status: String,
+}
+
+pub fn browse(path: &Path, config: &Config) -> Result<()> {
+    let store = CommandStore::load(path)?;
+    if store.commands.is_empty() {
+        println!("No commands saved.");
+        return Ok(());
+    }
+
+    let mut browser = Browser {
+        store,
+        sort: config.sort,
+        query: String::new(),
+        matches: Vec::new(),
+        state: ListState::default(),
+        mode: Mode::Normal,
+        status: String::new(),
+    };
+    browser.refresh();
+    let mut terminal = ratatui::init();
+    let result = browser.run(&mut terminal, path);
+    ratatui::restore();
+
+    // Enter leaves the browser, so placeholders and the confirmation use the normal terminal
+    if let Some(cmd) = result? {
+        let args = RunArgs { yes: config.confirm_run == Some(false), ..Default::default() };
+        return run_selected(path, browser.store, cmd, args);
+    }
+    Ok(())
+}
+
+impl Browser {
+    // Handle keys until the browser is closed, returning the command to run if any
+    fn run(&mut self, terminal: &mut DefaultTerminal, path: &Path) -> Result<Option<String>> {
+        loop {
+            terminal.draw(|frame| self.draw(frame)).context("Failed to draw the browser")?;
+            let Event::Key(key) = event::read().context("Failed to read a key")? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+                return Ok(None);
+            }
+            self.status.clear();
+
+            match std::mem::replace(&mut self.mode, Mode::Normal) {
+                Mode::Normal => match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(None),
+                    KeyCode::Enter => {
+                        if let Some(cmd) = self.selected() {
+                            return Ok(Some(cmd));
+                        }
+                    }
+                    KeyCode::Up | KeyCode::Char('k') => self.state.select_previous(),
+                    KeyCode::Down | KeyCode::Char('j') => self.state.select_next(),
+                    KeyCode::Char('/') => self.mode = Mode::Search,
+                    KeyCode::Char('c') => {
+                        if let Some(cmd) = self.selected() {
+                            self.status = match copy_to_clipboard(&cmd) {
+                                Ok(()) => format!("Copied to clipboard: {}", mask_secrets(&cmd)),
+                                Err(err) => format!("{:#}", err),
+                            };
+                        }
+                    }
+                    KeyCode::Char('e') => {
+                        if let Some(cmd) = self.selected() {
+                            let description = &self.store.commands[&cmd].description;
+                            // The one-line box would join the lines of a longer description
+                            if description.contains('\n') {
+                                self.status = "This description has several lines, use `keepc describe` to change it".to_string();
+                            } else {
+                                self.mode = Mode::Describe(description.clone());
+                            }
+                        }
+                    }
+                    KeyCode::Char('d') => {
+                        if self.selected().is_some() {
+                            self.mode = Mode::ConfirmDelete;
+                        }
+                    }
+                    _ => {}
+                },
+                Mode::Search => {
+                    match key.code {
+                        KeyCode::Esc | KeyCode::Enter => continue,
+                        KeyCode::Up => self.state.select_previous(),
+                        KeyCode::Down => self.state.select_next(),
+                        KeyCode::Backspace => {
+                            self.query.pop();
+                            self.refresh();
+                        }
+                        KeyCode::Char(c) => {
+                            self.query.push(c);
+                            self.refresh();
+                        }
+                        _ => {}
+                    }
+                    self.mode = Mode::Search;
+                }
+                Mode::Describe(mut description) => match key.code {
+                    KeyCode::Esc => {}
+                    KeyCode::Enter => {
+                        if let Some(cmd) = self.selected()
+                            && let Some(entry) = self.store.commands.get_mut(&cmd) {
+                            entry.description = description.trim().to_string();
+                            self.store.save(path)?;
+                            self.status = format!("Updated: {}", mask_secrets(&cmd));
+                        }
+                    }
+                    KeyCode::Backspace => {
+                        description.pop();
+                        self.mode = Mode::Describe(description);
+                    }
+                    KeyCode::Char(c) => {
+                        description.push(c);
+                        self.mode = Mode::Describe(description);
+                    }
+                    _ => self.mode = Mode::Describe(description),
+                },
+                Mode::ConfirmDelete => {
+                    if key.code == KeyCode::Char('y')
+                        && let Some(cmd) = self.selected() {
+                        self.store.commands.shift_remove(&cmd);
+                        let pruned = self.store.prune_sequences();
+                        self.store.save_with_backup(path)?;
+                        self.refresh();
+                        self.status = format!("Deleted: {} (`keepc undo` restores it)", mask_secrets(&cmd));
+                        if !pruned.is_empty() {
+                            self.status += &format!(", also removed from the sequences {}", pruned.join(", "));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Match the commands against the search box, keeping the highlight in range
+    fn refresh(&mut self) {
+        self.matches = if self.query.trim().is_empty() {
+            listed_commands(&self.store, self.sort).into_iter().cloned().collect()
+        } else {
+            search_logic(self.query.clone(), &self.store, &SearchOptions::default()).unwrap_or_default()
+        };
+        let selected = self.state.selected().unwrap_or(0).min(self.matches.len().saturating_sub(1));
+        self.state.select((!self.matches.is_empty()).then_some(selected));
+    }
+
+    fn selected(&self) -> Option<String> {
+        self.state.selected().and_then(|i| self.matches.get(i)).cloned()
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [search_area, list_area, help_area] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(1),
+            Constraint::Length(1),
+        ]).areas(frame.area());
+
+        let search_style = match self.mode {
+            Mode::Search => Style::new().fg(Color::Yellow),
+            _ => Style::new(),
+        };
+        let search = Paragraph::new(self.query.as_str())
+        .block(Block::bordered().title(" Search (/) ").border_style(search_style));
+        frame.render_widget(search, search_area);
+
+        let items: Vec<ListItem> = self.matches.iter().map(|cmd| {
+            let entry = &self.store.commands[cmd];
+            let mut spans = vec![Span::styled(format!("$ {}", mask_secrets(cmd)), Style::new().fg(Color::LightGreen))];
+            if !entry.description.is_empty() {
+                spans.push(Span::styled(format!(": {}", entry.description.replace('\n', " ")), Style::new().fg(Color::Blue)));
+            }
+            if entry.pinned {
+                spans.insert(0, Span::styled("★ ", Style::new().fg(Color::Yellow)));
+            }
+            ListItem::new(Line::from(spans))
+        }).collect();
+        let title = format!(" {} of {} commands ", self.matches.len(), self.store.commands.len());
+        let list = List::new(items)
+        .block(Block::bordered().title(title))
+        .highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+        .highlight_symbol("> ");
+        frame.render_stateful_widget(list, list_area, &mut self.state);
+
+        let help = match &self.mode {
+            Mode::Describe(description) => format!("Description: {}_  (Enter saves, Esc cancels)", description),
+            Mode::ConfirmDelete => "Delete the highlighted command? (y/n)".to_string(),
+            Mode::Search => "Type to search, Enter or Esc goes back to the list".to_string(),
+            Mode::Normal if !self.status.is_empty() => self.status.clone(),
+            Mode::Normal => "Enter run  c copy  e describe  d delete  / search  q quit".to_string(),
+        };
+        frame.render_widget(Paragraph::new(help).style(Style::new().add_modifier(Modifier::DIM)), help_area);
+    }
+}
@@ -1,52 +1,490 @@
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand, CommandFactory};
-use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Local, Utc};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use serde::{Deserialize, Deserializer, Serialize};
 use std::collections::HashMap;
+use indexmap::IndexMap;
 use std::fs::{self, File};
-use std::io::Write;
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode, ExitStatus, Stdio};
 use tempfile::NamedTempFile;
-use colored::Colorize;
+use colored::{ColoredString, Colorize};
+use dialoguer::{FuzzySelect, MultiSelect};
+
+#[cfg(feature = "tui")]
+mod browse;
+#[cfg(feature = "tui")]
+use browse::browse;
+use fuzzy_matcher::FuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use regex::{Regex, RegexBuilder};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+// Set by --quiet to silence informational messages
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+// Number of timestamped backups kept of saves that change the commands, 0 when `backups`
+// is off in config.toml
+static BACKUPS: AtomicUsize = AtomicUsize::new(0);
+
+// Set once an encrypted store is loaded, so commands typed at prompts don't end up in the
+// plain text history
+static NO_HISTORY: AtomicBool = AtomicBool::new(false);
+
+// Print an informational message like `Saved: ...`, unless --quiet was given. Command
+// output, prompts and errors always use println! or eprintln!.
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if !QUIET.load(Ordering::Relaxed) {
+            println!($($arg)*);
+        }
+    };
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct CommandEntry {
+    description: String,
+    // Longer free-form text, e.g. usage examples, only shown by `show`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    notes: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    aliases: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    created_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_used: Option<DateTime<Utc>>,
+    #[serde(default)]
+    use_count: u32,
+    // Exit code of the last run, missing when it was killed by a signal
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_exit: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_duration_ms: Option<u64>,
+    // The end of the output of the last `run --capture`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_output: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pinned: bool,
+    // Needs a typed `yes` before running, see `is_dangerous`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    dangerous: bool,
+    // Directory the command is run from, `~` allowed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cwd: Option<String>,
+    // Environment variables set when running the command. A POSIX shell expands `$VAR`
+    // references in values, cmd and PowerShell get them as they are.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    env: HashMap<String, String>,
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 struct CommandStore {
-    commands: HashMap<String, String>,
+    #[serde(deserialize_with = "deserialize_commands")]
+    // Kept in the order the commands were added
+    commands: IndexMap<String, CommandEntry>,
+    // Named lists of commands that are run one after another
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    sequences: HashMap<String, Vec<String>>,
+    // Whether the file is encrypted with a passphrase, see `keepc init --encrypt`
+    #[serde(skip)]
+    encrypted: bool,
+}
+
+// Accept both the old `"command": "description"` format and the new entry struct
+fn deserialize_commands<'de, D>(deserializer: D) -> Result<IndexMap<String, CommandEntry>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StoredEntry {
+        Legacy(String),
+        Entry(CommandEntry),
+    }
+
+    let stored: IndexMap<String, StoredEntry> = IndexMap::deserialize(deserializer)?;
+    Ok(stored.into_iter().map(|(cmd, entry)| {
+        let entry = match entry {
+            StoredEntry::Legacy(description) => CommandEntry { description, ..Default::default() },
+            StoredEntry::Entry(entry) => entry,
+        };
+        (cmd, entry)
+    }).collect())
 }
 
 impl CommandStore {
     fn new() -> Self {
         Self {
-            commands: HashMap::new(),
+            commands: IndexMap::new(),
+            sequences: HashMap::new(),
+            encrypted: false,
         }
     }
 
-    fn load(path: &PathBuf) -> Result<Self> {
+    fn load(path: &Path) -> Result<Self> {
         if !path.exists() {
             return Ok(Self::new());
         }
 
-        let file = File::open(path).context("Failed to open commands file")?;
-        let store: CommandStore =
-        serde_json::from_reader(file).context("Failed to parse commands file")?;
-        Ok(store)
+        let bytes = fs::read(path).context("Failed to open commands file")?;
+        if bytes.starts_with(ENCRYPTED_HEADER) {
+            let mut store = Self::parse(&decrypt_store(&bytes)?, path).context("Failed to parse commands file")?;
+            store.encrypted = true;
+            NO_HISTORY.store(true, Ordering::Relaxed);
+            return Ok(store);
+        }
+        Self::parse(&bytes, path).context("Failed to parse commands file")
+    }
+
+    // Read another commands file to import or merge, decrypting it the way `load` does
+    fn read_other(file: &Path) -> Result<Self> {
+        let mut bytes = fs::read(file).with_context(|| format!("Failed to open {}", file.display()))?;
+        if bytes.starts_with(ENCRYPTED_HEADER) {
+            bytes = decrypt_store(&bytes).with_context(|| format!("Failed to decrypt {}", file.display()))?;
+        }
+        Self::parse(&bytes, file).with_context(|| format!("Failed to parse {}", file.display()))
+    }
+
+    // Read a store in the format of its file: YAML for `.yaml` and `.yml`, JSON otherwise
+    fn parse(bytes: &[u8], path: &Path) -> Result<Self> {
+        Ok(match StoreFormat::of(path) {
+            StoreFormat::Json => serde_json::from_slice(bytes)?,
+            StoreFormat::Yaml => serde_norway::from_slice(bytes)?,
+        })
+    }
+
+    fn serialize(&self, path: &Path) -> Result<Vec<u8>> {
+        Ok(match StoreFormat::of(path) {
+            StoreFormat::Json => serde_json::to_vec_pretty(self)?,
+            StoreFormat::Yaml => serde_norway::to_string(self)?.into_bytes(),
+        })
+    }
+
+    // Save changed commands, also keeping a timestamped copy when `backups` is on
+    fn save(&self, path: &Path) -> Result<()> {
+        self.save_usage(path)?;
+        let keep = BACKUPS.load(Ordering::Relaxed);
+        if keep > 0 {
+            rotate_backups(path, keep)?;
+        }
+        Ok(())
     }
 
-    fn save(&self, path: &PathBuf) -> Result<()> {
-        // Ensure parent directory exists
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent).context("Failed to create directory")?;
+    // Save only the run statistics of `run`, which don't deserve a backup that pushes
+    // out older ones
+    fn save_usage(&self, path: &Path) -> Result<()> {
+        let mut content = self.serialize(path).context("Failed to serialize commands")?;
+        if self.encrypted {
+            content = encrypt_store(&content)?;
         }
+        write_store_file(path, &content)
+    }
 
-        let file = File::create(path).context("Failed to create commands file")?;
-        serde_json::to_writer_pretty(file, self).context("Failed to write commands")?;
+    // Move a commands file that can't be parsed aside, so one bad edit doesn't make
+    // every subcommand fail
+    fn recover(path: &Path) -> Result<()> {
+        // An encrypted file can't be checked without the passphrase
+        if is_encrypted_file(path) {
+            return Ok(());
+        }
+        let Ok(bytes) = fs::read(path) else {
+            return Ok(());
+        };
+        if let Err(err) = Self::parse(&bytes, path) {
+            // Timestamped, so a second corrupt file doesn't overwrite the first. A symlinked
+            // store keeps its link, the next save recreates the file it points to.
+            let target = resolve_link(path);
+            let corrupt = with_suffix(&target, &format!(".corrupt-{}", Local::now().format("%Y%m%d-%H%M%S-%3f")));
+            fs::rename(&target, &corrupt).context("Failed to move corrupt commands file")?;
+            eprintln!("{} {} could not be parsed ({}). It was moved to {} and an empty store is used instead.",
+            "Warning:".yellow(), path.display(), err, corrupt.display());
+        }
         Ok(())
     }
+
+    // Point sequence steps at the new text of a renamed or merged command
+    fn rename_in_sequences(&mut self, old: &str, new: &str) {
+        for step in self.sequences.values_mut().flatten().filter(|step| *step == old) {
+            *step = new.to_string();
+        }
+    }
+
+    // Drop sequence steps whose command was deleted, and sequences left without steps,
+    // returning the names of the sequences that changed
+    fn prune_sequences(&mut self) -> Vec<String> {
+        let commands = &self.commands;
+        let mut changed = Vec::new();
+        for (name, steps) in self.sequences.iter_mut() {
+            let before = steps.len();
+            steps.retain(|step| commands.contains_key(step));
+            if steps.len() < before {
+                changed.push(name.clone());
+            }
+        }
+        self.sequences.retain(|_, steps| !steps.is_empty());
+        sort_commands(&mut changed);
+        changed
+    }
+
+    // Keep the previous file as a backup for `undo` before a destructive save
+    fn save_with_backup(&self, path: &Path) -> Result<()> {
+        if path.exists() {
+            fs::copy(path, with_suffix(path, ".bak")).context("Failed to back up commands file")?;
+        }
+        self.save(path)
+    }
+}
+
+// Replace the contents of a commands file. A temporary file is written next to it and
+// renamed over it, so an interrupted write can never leave a half-written file behind.
+fn write_store_file(path: &Path, content: &[u8]) -> Result<()> {
+    // Write through a symlinked store, e.g. into a dotfiles repo, instead of replacing
+    // the link with a regular file
+    let target = resolve_link(path);
+
+    // Ensure parent directory exists
+    let parent = target.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    fs::create_dir_all(parent).context("Failed to create directory")?;
+
+    let mut file = NamedTempFile::new_in(parent).context("Failed to create temporary file")?;
+    file.write_all(content).context("Failed to write commands")?;
+    file.as_file().sync_all().context("Failed to write commands")?;
+    file.persist(&target).context("Failed to replace commands file")?;
+    Ok(())
+}
+
+// The file a symlinked store points to, or the path itself
+fn resolve_link(path: &Path) -> PathBuf {
+    if let Ok(target) = fs::canonicalize(path) {
+        return target;
+    }
+    // A link whose file doesn't exist yet, e.g. after `recover` moved it aside
+    match fs::read_link(path) {
+        Ok(target) => path.parent().unwrap_or(Path::new(".")).join(target),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+// Every age-encrypted file starts with this line
+const ENCRYPTED_HEADER: &[u8] = b"age-encryption.org/v1";
+
+fn is_encrypted_file(path: &Path) -> bool {
+    let mut header = [0; ENCRYPTED_HEADER.len()];
+    File::open(path).and_then(|mut file| std::io::Read::read_exact(&mut file, &mut header)).is_ok()
+        && header == ENCRYPTED_HEADER
+}
+
+// The passphrase is asked for at most once per invocation
+#[cfg(feature = "encryption")]
+static PASSPHRASE: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+
+// Take the passphrase from $KEEPC_PASSPHRASE, else ask for it. A new passphrase has to be
+// typed twice.
+#[cfg(feature = "encryption")]
+fn store_passphrase(new: bool) -> Result<age::secrecy::SecretString> {
+    if let Some(passphrase) = PASSPHRASE.get() {
+        return Ok(passphrase.clone().into());
+    }
+    let passphrase = match std::env::var("KEEPC_PASSPHRASE").ok().filter(|value| !value.is_empty()) {
+        Some(passphrase) => passphrase,
+        None if new => dialoguer::Password::new()
+        .with_prompt("New passphrase")
+        .with_confirmation("Repeat passphrase", "Passphrases don't match")
+        .interact()
+        .context("Failed to read passphrase")?,
+        None => dialoguer::Password::new()
+        .with_prompt("Passphrase")
+        .interact()
+        .context("Failed to read passphrase")?,
+    };
+    if passphrase.is_empty() {
+        return Err(anyhow::anyhow!("Passphrase cannot be empty"));
+    }
+    Ok(PASSPHRASE.get_or_init(|| passphrase).clone().into())
+}
+
+#[cfg(feature = "encryption")]
+fn encrypt_store(json: &[u8]) -> Result<Vec<u8>> {
+    let encryptor = age::Encryptor::with_user_passphrase(store_passphrase(false)?);
+    let mut encrypted = Vec::new();
+    let mut writer = encryptor.wrap_output(&mut encrypted).context("Failed to encrypt commands")?;
+    writer.write_all(json).context("Failed to encrypt commands")?;
+    writer.finish().context("Failed to encrypt commands")?;
+    Ok(encrypted)
+}
+
+#[cfg(feature = "encryption")]
+fn decrypt_store(bytes: &[u8]) -> Result<Vec<u8>> {
+    let decryptor = age::Decryptor::new(bytes).context("Failed to read encrypted commands file")?;
+    let identity = age::scrypt::Identity::new(store_passphrase(false)?);
+    let mut reader = decryptor.decrypt(std::iter::once(&identity as &dyn age::Identity))
+    .context("Failed to decrypt commands file. Is the passphrase right?")?;
+    let mut json = Vec::new();
+    std::io::Read::read_to_end(&mut reader, &mut json).context("Failed to decrypt commands file")?;
+    Ok(json)
+}
+
+#[cfg(not(feature = "encryption"))]
+fn encrypt_store(_json: &[u8]) -> Result<Vec<u8>> {
+    Err(anyhow::anyhow!("keepc was built without encryption support"))
+}
+
+#[cfg(not(feature = "encryption"))]
+fn decrypt_store(_bytes: &[u8]) -> Result<Vec<u8>> {
+    Err(anyhow::anyhow!("The commands file is encrypted, but keepc was built without encryption support"))
+}
+
+// How a commands file is written, chosen by its extension
+#[derive(Clone, Copy)]
+enum StoreFormat {
+    Json,
+    Yaml,
+}
+
+impl StoreFormat {
+    fn of(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()).map(|ext| ext.to_lowercase()).as_deref() {
+            Some("yaml" | "yml") => StoreFormat::Yaml,
+            _ => StoreFormat::Json,
+        }
+    }
+}
+
+// The path of a file kept next to the store, e.g. `commands.json.bak`
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+// Copy the commands file to `backups/<name>-<timestamp>.<ext>` next to it, then delete
+// the oldest copies of that file beyond `keep`
+fn rotate_backups(path: &Path, keep: usize) -> Result<()> {
+    let dir = backup_dir(path);
+    fs::create_dir_all(&dir).context("Failed to create backup directory")?;
+    let (stem, extension) = backup_name_parts(path);
+    let backup = dir.join(format!("{}-{}{}", stem, Local::now().format("%Y%m%d-%H%M%S-%3f"), extension));
+    fs::copy(path, &backup).context("Failed to back up commands file")?;
+
+    let backups = list_backups(path)?;
+    for old in &backups[..backups.len().saturating_sub(keep)] {
+        fs::remove_file(old).with_context(|| format!("Failed to delete old backup {}", old.display()))?;
+    }
+    Ok(())
+}
+
+fn backup_dir(path: &Path) -> PathBuf {
+    path.parent().unwrap_or(Path::new(".")).join("backups")
+}
+
+// The file stem and the extension with its dot, e.g. `commands` and `.json`
+fn backup_name_parts(path: &Path) -> (String, String) {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+    let extension = path.extension().map(|ext| format!(".{}", ext.to_string_lossy())).unwrap_or_default();
+    (stem, extension)
+}
+
+// The timestamped backups of one commands file, oldest first. Only exact
+// `<stem>-<timestamp><ext>` names count, so profile `work` leaves those of `work-2` alone.
+fn list_backups(path: &Path) -> Result<Vec<PathBuf>> {
+    let dir = backup_dir(path);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let (stem, extension) = backup_name_parts(path);
+    let pattern = Regex::new(&format!(r"^{}-\d{{8}}-\d{{6}}-\d{{3}}{}$", regex::escape(&stem), regex::escape(&extension)))
+    .context("Failed to build backup name pattern")?;
+    let mut backups: Vec<PathBuf> = fs::read_dir(&dir).context("Failed to read backup directory")?
+    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+    .filter(|file| file.file_name().is_some_and(|name| pattern.is_match(&name.to_string_lossy())))
+    .collect();
+    // The timestamps sort oldest first by name
+    backups.sort();
+    Ok(backups)
+}
+
+// Defaults read from `keepc/config.toml`. A command line flag beats an environment
+// variable, which beats this file, which beats the built-in default.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct Config {
+    // Editor command for `edit`
+    editor: Option<String>,
+    // Force colors on or off instead of detecting the terminal
+    color: Option<bool>,
+    // Order of `list` output
+    sort: SortOrder,
+    // Whether `run` asks before executing
+    confirm_run: Option<bool>,
+    // Format of each listed command, like --template
+    template: Option<String>,
+    // Whether every save also keeps a timestamped copy in `keepc/backups`
+    backups: bool,
+    // How many of those copies to keep
+    max_backups: Option<usize>,
+}
+
+impl Config {
+    fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(path).context("Failed to read config file")?;
+        toml::from_str(&content).with_context(|| format!("Failed to parse config file {}", path.display()))
+    }
+}
+
+#[derive(Clone, Copy, Default, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+enum SortOrder {
+    // Alphabetically by command
+    #[default]
+    Name,
+    // Most run first
+    Uses,
+    // Most recently run first
+    Recent,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum AddedOrder {
+    // Oldest first
+    Added,
+    // Newest first
+    Recent,
 }
 
+// Version with the commit and date of the build, set by build.rs
+const VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), " (", env!("KEEPC_GIT_HASH"), " ", env!("KEEPC_BUILD_DATE"), ")");
+
 #[derive(Parser)]
-#[command(name = "keepc", about = "Keep and manage useful commands")]
+#[command(name = "keepc", version = VERSION, about = "Keep and manage useful commands",
+after_help = "Exit status: 0 on success, 1 when no saved command matches the pattern of grep, run or remove, 2 on any other error.")]
 struct Cli {
+    #[arg(long, global = true, help = "Use a named command store instead of the default one")]
+    profile: Option<String>,
+    #[arg(long = "file", global = true, value_name = "PATH", conflicts_with = "profile",
+    help = "Use this commands file instead of the default one [env: KEEPC_FILE]")]
+    store_file: Option<PathBuf>,
+    #[arg(long, global = true, help = "Fail instead of recovering from a corrupt commands file")]
+    strict: bool,
+    #[arg(long, global = true, help = "Print plain, uncolored output")]
+    plain: bool,
+    #[arg(short, long, global = true, help = "Don't print informational messages like `Saved: ...`")]
+    quiet: bool,
+    #[arg(long, global = true, help = "Show {{secret:name}} placeholders instead of ****")]
+    reveal: bool,
+    #[arg(long, global = true, value_name = "FORMAT", conflicts_with = "plain",
+    help = "Print each command with a format like \"{cmd}  # {desc}\", using {cmd}, {desc}, {tags} and {uses}")]
+    template: Option<String>,
+    // Options for the implicit search, e.g. `keepc -s AWS`
+    #[command(flatten)]
+    search: SearchOptions,
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -55,290 +493,3529 @@ struct Cli {
 enum Commands {
     // Add a new command
     #[command(about = "Add a new command")]
-    New {
-        command: Option<String>,
-        description: Option<String>,
-    },
+    New(NewArgs),
     #[command(hide = true)]
-    Add {
-        command: Option<String>,
-        description: Option<String>,
-    },
+    Add(NewArgs),
+    // Save the last command from the shell history
+    #[command(about = "Save the last command from your shell history")]
+    Last,
     // List all commands
     #[command(about = "List all saved commands")]
-    List,
+    List(ListArgs),
     #[command(hide = true)]
-    Ls,
+    Ls(ListArgs),
     // Search for a command
     #[command(about = "Search for commands matching a pattern")]
-    Grep { pattern: String },
+    Grep(GrepArgs),
     #[command(hide = true)]
-    Find { pattern: String },
+    Find(GrepArgs),
     #[command(hide = true)]
-    Search { pattern: String },
+    Search(GrepArgs),
+    // Show everything stored about a command
+    #[command(about = "Show the details of a saved command")]
+    Show { pattern: String },
     // Delete a command
     #[command(about = "Delete a saved command")]
-    Remove { pattern: String },
+    Remove(RemoveArgs),
     #[command(hide = true)]
-    Rm { pattern: String },
+    Rm(RemoveArgs),
     #[command(hide = true)]
-    Delete { pattern: String },
+    Delete(RemoveArgs),
+    // Change the command string of a saved command
+    #[command(about = "Change the command string of a saved command")]
+    Rename { pattern: String },
+    // Change the description of a saved command
+    #[command(about = "Change the description of a saved command")]
+    Describe { pattern: String },
+    // Save a changed copy of a command
+    #[command(about = "Copy a saved command as the start of a new one")]
+    Duplicate { pattern: String },
+    #[command(hide = true)]
+    Cp { pattern: String },
+    // Merge commands that only differ in case or whitespace
+    #[command(about = "Find and merge duplicate commands")]
+    Dedup {
+        #[arg(long, help = "Only report the duplicates")]
+        dry_run: bool,
+    },
+    // Fill in missing descriptions
+    #[command(about = "Add descriptions to commands that have none")]
+    Annotate,
+    // Add or remove tags of every matching command
+    #[command(about = "Add or remove tags of all commands matching a pattern")]
+    Tag {
+        pattern: String,
+        #[arg(long = "add", value_name = "TAG", help = "Tag to add (repeatable)")]
+        add: Vec<String>,
+        #[arg(long = "remove", value_name = "TAG", help = "Tag to remove (repeatable)")]
+        remove: Vec<String>,
+    },
+    // List the tags in use
+    #[command(about = "List all tags with the number of commands using them")]
+    Tags,
+    // Keep a command at the top of the list
+    #[command(about = "Pin a command to the top of the list")]
+    Pin { pattern: String },
+    // Stop keeping a command at the top of the list
+    #[command(about = "Unpin a pinned command")]
+    Unpin { pattern: String },
+    // Change where a command is in the saved order
+    #[command(about = "Move a command within the order shown by `list --order added`")]
+    Move {
+        pattern: String,
+        #[command(flatten)]
+        direction: MoveDirection,
+    },
     // Edit commands in a text editor
     #[command(about = "Edit commands in a text editor")]
-    Edit,
+    Edit {
+        #[arg(long, help = "Editor command to use instead of $VISUAL or $EDITOR")]
+        editor: Option<String>,
+    },
     // Execute a saved command
     #[command(about = "Execute a saved command")]
-    Run { pattern: String },
+    Run(RunArgs),
     #[command(hide = true)]
-    Execute { pattern: String },
+    Execute(RunArgs),
+    // Copy a saved command to the clipboard
+    #[command(about = "Copy a saved command to the clipboard")]
+    Copy { pattern: String },
+    // List the most used commands
+    #[command(about = "List commands by how often they were run")]
+    Top,
+    // List the most recently used commands
+    #[command(about = "List commands by when they were last run")]
+    Recent {
+        #[arg(long, value_name = "N", default_value_t = 10, help = "Show at most N commands")]
+        limit: usize,
+    },
+    // Count saved commands
+    #[command(about = "Print the number of saved commands")]
+    Count {
+        #[arg(long, help = "Also count the commands of each tag")]
+        by_tag: bool,
+    },
+    // Summarize the saved commands
+    #[command(about = "Show statistics about the saved commands")]
+    Stats,
+    // Full-screen list to search, run, copy, describe and delete commands
+    #[command(about = "Browse the saved commands in a full-screen list")]
+    Browse,
+    // Export commands to stdout
+    #[command(about = "Export saved commands to stdout")]
+    Export {
+        #[arg(long, value_enum, default_value_t = ExportFormat::Json, help = "Output format")]
+        format: ExportFormat,
+    },
+    // Import commands from a file
+    #[command(about = "Import commands from a JSON or YAML file")]
+    Import {
+        #[arg(required_unless_present = "dir")]
+        file: Option<PathBuf>,
+        #[arg(long, value_name = "DIR", conflicts_with = "file", help = "Import every .sh snippet in a directory")]
+        dir: Option<PathBuf>,
+        #[arg(long, value_enum, default_value_t = ImportMode::Skip, help = "What to do when a command already exists")]
+        mode: ImportMode,
+    },
+    // Combine another commands file with this one, asking about conflicts
+    #[command(about = "Merge another commands file, choosing between conflicting descriptions")]
+    Merge { file: PathBuf },
+    // Generate shell completions
+    #[command(about = "Generate a shell completion script")]
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    // Delete every saved command
+    #[command(about = "Delete all saved commands")]
+    Clear {
+        #[arg(long, help = "Copy the commands file to a timestamped backup first")]
+        backup: bool,
+        #[arg(long, help = "Delete the commands file itself instead of emptying it")]
+        remove_file: bool,
+    },
+    // Undo the last destructive change
+    #[command(about = "Undo the last delete, edit, import or overwrite")]
+    Undo,
+    // Create the store or change its encryption
+    #[command(about = "Create the commands file, or encrypt or decrypt it")]
+    Init {
+        #[arg(long, help = "Encrypt the commands file with a passphrase")]
+        encrypt: bool,
+        #[arg(long, conflicts_with = "encrypt", help = "Store the commands file unencrypted again")]
+        decrypt: bool,
+    },
+    // Print version and build information
+    #[command(about = "Print version and build information")]
+    Version {
+        #[arg(short, long, help = "Also print the rustc version and target")]
+        verbose: bool,
+    },
+    // Print where keepc looks for things, for debugging
+    #[command(about = "Show the files, editor and shell keepc uses")]
+    Doctor,
+    // Open where the commands are saved with the platform's opener
+    #[command(about = "Open the folder of the commands file, or the file itself")]
+    Open {
+        #[arg(value_enum, default_value_t = OpenTarget::Dir, help = "What to open")]
+        target: OpenTarget,
+    },
+    // Manage named command stores
+    #[command(about = "Manage named command stores", subcommand)]
+    Profile(ProfileCommands),
+    // Run saved commands one after another
+    #[command(about = "Manage and run sequences of saved commands", subcommand)]
+    Seq(SeqCommands),
+    // Anything that isn't a subcommand searches saved commands
+    #[command(external_subcommand)]
+    Pattern(Vec<String>),
 }
 
-fn get_commands_file() -> Result<PathBuf> {
-    let mut path = dirs::config_dir().context("Could not determine config directory")?;
-    path.push("keepc");
-    Ok(path.join("commands.json"))
+impl Commands {
+    // Whether the subcommand reads the commands file, so a corrupt one needs recovering
+    // first. The others must not move the user's store aside.
+    fn loads_store(&self) -> bool {
+        !matches!(self, Commands::Completions { .. } | Commands::Version { .. } | Commands::Doctor
+            | Commands::Open { .. } | Commands::Profile(_))
+    }
 }
 
-// Find all commands that match the pattern. Used in List, search and delete commands.
-fn search_logic(pattern: String, store: &CommandStore) -> Vec<String> {
-    let keywords: Vec<&str> = pattern.split_whitespace().collect();
-    let mut matching_commands = Vec::new();
+// Arguments of `new` and its alias
+#[derive(Args, Default)]
+struct NewArgs {
+    command: Option<String>,
+    description: Option<String>,
+    // Anything after the description means the command wasn't quoted
+    #[arg(hide = true)]
+    extra: Vec<String>,
+    // `keepc new -- git log --oneline` saves everything after `--` as the command
+    #[arg(last = true, value_name = "COMMAND", conflicts_with_all = ["command", "stdin"])]
+    words: Vec<String>,
+    #[arg(long = "tag", help = "Tag the command (repeatable)")]
+    tags: Vec<String>,
+    #[arg(long = "alias", help = "Another name to find the command by (repeatable)")]
+    aliases: Vec<String>,
+    #[arg(long, value_name = "TEXT", help = "Longer notes, e.g. usage examples, shown by `keepc show`")]
+    note: Option<String>,
+    #[arg(long, value_name = "PATH", help = "Directory to run the command from")]
+    cwd: Option<String>,
+    #[arg(long = "env", value_name = "KEY=VALUE", value_parser = parse_env_var, help = "Set an environment variable when running the command, `$VAR` in the value is expanded (repeatable)")]
+    env: Vec<(String, String)>,
+    #[arg(long, help = "Ask for a typed 'yes' before running the command")]
+    dangerous: bool,
+    #[arg(short, long, help = "Overwrite an existing command without asking")]
+    force: bool,
+    #[arg(long, help = "Don't check that the program of the command is in PATH")]
+    no_check: bool,
+    #[arg(long, help = "Ask for the description before the command when prompting")]
+    desc_first: bool,
+    #[arg(long, conflicts_with = "command", help = "Read the command from stdin, e.g. `history | tail -1 | keepc new --stdin`")]
+    stdin: bool,
+}
 
-    for (cmd, desc) in &store.commands {
-        let matched_keywords = keywords.iter()
-        .filter(|keyword| {
-            cmd.to_lowercase().contains(&keyword.to_lowercase())
-            || desc.to_lowercase().contains(&keyword.to_lowercase())
-        }).count();
-        if matched_keywords == keywords.len() {
-            matching_commands.push(cmd.clone());
-        }
-    }
-    matching_commands
+// Arguments of `list` and its aliases
+#[derive(Args)]
+struct ListArgs {
+    #[arg(long = "tag", help = "Only show commands with this tag (repeatable)")]
+    tags: Vec<String>,
+    #[arg(long, help = "Print the commands as JSON")]
+    json: bool,
+    #[arg(short, long, help = "Show when each command was created and last used")]
+    verbose: bool,
+    #[arg(long, value_enum, help = "Sort order [default: name, or `sort` in config.toml]")]
+    sort: Option<SortOrder>,
+    #[arg(long, value_enum, conflicts_with_all = ["sort", "numbered"], help = "Show the commands in the order they were added")]
+    order: Option<AddedOrder>,
+    #[arg(long, value_name = "N", help = "Show at most N commands")]
+    limit: Option<usize>,
+    #[arg(long, help = "Print everything instead of using $PAGER when the list is taller than the terminal")]
+    no_pager: bool,
+    // Filters and --sort would renumber the commands away from what `run --from-list` picks
+    #[arg(short, long, conflicts_with_all = ["tags", "sort", "since", "last_used_before"],
+    help = "Number the commands, for use with `run --from-list`")]
+    numbered: bool,
+    #[arg(long, conflicts_with = "json", help = "Show the commands under a header for each tag")]
+    group_by_tag: bool,
+    #[arg(long, conflicts_with = "json", help = "Show the {{placeholders}} each command asks for")]
+    show_placeholders: bool,
+    #[arg(long, value_name = "WHEN", value_parser = parse_time,
+    help = "Only show commands saved since a date like 2024-01-01 or a time ago like 30d or 2w")]
+    since: Option<DateTime<Utc>>,
+    #[arg(long, value_name = "WHEN", value_parser = parse_time,
+    help = "Only show commands last run before a date or a time ago, e.g. 90d to find unused ones")]
+    last_used_before: Option<DateTime<Utc>>,
+    #[arg(long, help = "Also show commands without the date --since or --last-used-before looks at")]
+    include_undated: bool,
 }
 
-fn new_command(command: Option<String>, description: Option<String>) -> Result<()> {
-    use std::io::{self, BufRead};
-    let path = get_commands_file()?;
-    let mut store = CommandStore::load(&path)?;
+// Arguments of `grep` and its aliases
+#[derive(Args)]
+struct GrepArgs {
+    pattern: String,
+    #[arg(long, value_name = "N", help = "Show at most N commands")]
+    limit: Option<usize>,
+    #[command(flatten)]
+    options: SearchOptions,
+}
 
-    // Get command from user
-    let command = match command {
-        Some(cmd) => cmd,
-        None => {
-            print!("Enter command: ");
-            io::stdout().flush()?;
-            let mut line = String::new();
-            io::stdin().lock().read_line(&mut line)?;
-            line.trim().to_string()
+// Where `move` puts a command, exactly one is required
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+struct MoveDirection {
+    #[arg(long, help = "Move the command one place up")]
+    up: bool,
+    #[arg(long, help = "Move the command one place down")]
+    down: bool,
+    #[arg(long, help = "Move the command to the start")]
+    top: bool,
+    #[arg(long, help = "Move the command to the end")]
+    bottom: bool,
+}
+
+// How saved commands are printed
+#[derive(Default)]
+struct OutputOptions {
+    // Uncolored `command<TAB>description` lines for feeding into other tools
+    plain: bool,
+    // Show `{{secret:name}}` placeholders instead of masking them
+    reveal: bool,
+    // Format of each command from --template or config.toml, replacing `$ cmd: desc`
+    template: Option<String>,
+}
+
+// Arguments of `remove` and its aliases
+#[derive(Args)]
+struct RemoveArgs {
+    #[arg(required_unless_present = "exact")]
+    pattern: Option<String>,
+    #[arg(long, help = "Delete every matching command")]
+    all: bool,
+    #[arg(short, long, conflicts_with = "all", help = "Check off the matching commands to delete")]
+    interactive: bool,
+    #[arg(long, value_name = "COMMAND", conflicts_with_all = ["pattern", "all", "interactive"], help = "Delete the command that is exactly COMMAND")]
+    exact: Option<String>,
+    #[command(flatten)]
+    search: SearchOptions,
+}
+
+// Arguments of `run` and its aliases
+#[derive(Args, Default)]
+struct RunArgs {
+    #[arg(help = "Pattern to search for; pick interactively when omitted")]
+    pattern: Option<String>,
+    #[arg(short, long, help = "Run without asking for confirmation")]
+    yes: bool,
+    #[arg(long, help = "Print the command instead of running it")]
+    dry_run: bool,
+    #[arg(long, value_name = "N", requires = "pattern", help = "Run the Nth match without asking which one")]
+    index: Option<usize>,
+    #[arg(long, requires = "pattern", conflicts_with = "index", help = "Run the first match without asking which one")]
+    first: bool,
+    #[arg(long, help = "Shell to run the command with [default: $SHELL, else sh or cmd]")]
+    shell: Option<String>,
+    #[arg(long, value_name = "N", conflicts_with_all = ["pattern", "index", "first"],
+    help = "Run the command numbered N by `keepc list --numbered`")]
+    from_list: Option<usize>,
+    #[arg(long, value_name = "PATH", help = "Run from this directory instead of the command's own, `~` and $VAR allowed")]
+    dir: Option<String>,
+    #[arg(long, help = "Collect the output and print it labeled once the command is done")]
+    capture: bool,
+    #[arg(long, conflicts_with = "shell", help = "Run the program directly instead of through a shell, when the command uses no shell syntax")]
+    no_shell: bool,
+    #[arg(last = true, value_name = "ARGS", help = "Arguments to append to the command, e.g. `keepc run pods -- -n prod`")]
+    extra_args: Vec<String>,
+    #[command(flatten)]
+    search: SearchOptions,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    Json,
+    Yaml,
+    Sh,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OpenTarget {
+    // The folder containing the commands file
+    Dir,
+    // The commands file
+    File,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ImportMode {
+    // Keep the existing command
+    Skip,
+    // Replace the existing command with the imported one
+    Overwrite,
+    // Import the command under a new name
+    Rename,
+}
+
+#[derive(Subcommand)]
+enum ProfileCommands {
+    // List all stores
+    #[command(about = "List all command stores")]
+    List,
+}
+
+#[derive(Subcommand)]
+enum SeqCommands {
+    // Save a sequence
+    #[command(about = "Save a sequence of saved commands, e.g. `keepc seq new deploy \"make\" \"make test\"`")]
+    New {
+        name: String,
+        #[arg(required = true, help = "Saved commands to run in order, each quoted")]
+        commands: Vec<String>,
+        #[arg(short, long, help = "Overwrite an existing sequence without asking")]
+        force: bool,
+    },
+    // Run a sequence
+    #[command(about = "Run the commands of a sequence in order")]
+    Run {
+        name: String,
+        #[arg(short, long, help = "Run without asking for confirmation")]
+        yes: bool,
+        #[arg(long = "continue", help = "Keep going when a command fails")]
+        keep_going: bool,
+        #[arg(long, help = "Shell to run the commands with [default: $SHELL, else sh or cmd]")]
+        shell: Option<String>,
+    },
+    // List sequences
+    #[command(about = "List saved sequences")]
+    List,
+    // Delete a sequence
+    #[command(about = "Delete a saved sequence")]
+    Remove { name: String },
+}
+
+// Name of the store used when no profile is selected
+const DEFAULT_PROFILE: &str = "commands";
+
+fn get_config_dir() -> Result<PathBuf> {
+    let mut path = dirs::config_dir().context("Could not determine config directory")?;
+    path.push("keepc");
+    Ok(path)
+}
+
+// Resolve the commands file: --file, then --profile, then $KEEPC_FILE, then the default
+// store in the config directory
+fn get_commands_file(file: Option<&Path>, profile: Option<&str>) -> Result<PathBuf> {
+    if let Some(file) = file {
+        return Ok(file.to_path_buf());
+    }
+    if profile.is_none()
+        && let Some(file) = std::env::var_os("KEEPC_FILE").filter(|value| !value.is_empty()) {
+        return Ok(PathBuf::from(file));
+    }
+    let profile = profile.unwrap_or(DEFAULT_PROFILE);
+    if profile.is_empty() || profile.contains(['/', '\\']) {
+        return Err(anyhow::anyhow!("Invalid profile name: '{}'", profile));
+    }
+    // A profile is stored as JSON unless it was created as a YAML file
+    let dir = get_config_dir()?;
+    for ext in ["yaml", "yml"] {
+        let candidate = dir.join(format!("{}.{}", profile, ext));
+        if candidate.exists() {
+            return Ok(candidate);
         }
-    };
-    if command.is_empty() {
-        return Err(anyhow::anyhow!("Command cannot be empty"));
     }
+    Ok(dir.join(format!("{}.json", profile)))
+}
 
-    // Get description from user if provided
-    let description = match description {
-        Some(desc) => desc,
-        None => {
-            print!("Enter description (optional): ");
-            io::stdout().flush()?;
-            let mut line = String::new();
-            io::stdin().lock().read_line(&mut line)?;
-            line.trim().to_string()
-        }
-    };
-    store.commands.insert(command, description);
-    store.save(&path)?;
-    Ok(())
+// How a search pattern is matched against saved commands
+#[derive(Args, Default)]
+struct SearchOptions {
+    #[arg(short = 'e', long, help = "Treat the pattern as a regular expression")]
+    regex: bool,
+    #[arg(short = 's', long, help = "Match upper and lower case exactly")]
+    case_sensitive: bool,
+    #[arg(short = 'f', long, help = "Match approximately, best matches first")]
+    fuzzy: bool,
+    #[arg(short = 'w', long, conflicts_with = "fuzzy", help = "Only match whole words")]
+    word: bool,
+    #[arg(short = 'o', long, conflicts_with_all = ["regex", "fuzzy"], help = "Match commands containing any of the words instead of all of them")]
+    any: bool,
+    #[arg(long, value_enum, default_value_t = SearchField::Both, help = "Where to look for the pattern")]
+    field: SearchField,
+    #[arg(long, help = "Also search the output kept by `run --capture`")]
+    in_output: bool,
 }
 
-fn list_commands() -> Result<()> {
-    let path = get_commands_file()?;
-    let store = CommandStore::load(&path)?;
+// Which parts of a saved command a search looks at
+#[derive(Clone, Copy, Default, PartialEq, ValueEnum)]
+enum SearchField {
+    // The command text and its aliases
+    Command,
+    // The description
+    Description,
+    #[default]
+    Both,
+}
 
-    if store.commands.is_empty() {
-        println!("No commands saved.");
-        return Ok(());
+// The fields of an entry a search looks at. Aliases are searched just like the command text.
+fn search_fields<'a>(cmd: &'a str, entry: &'a CommandEntry, options: &SearchOptions) -> Vec<&'a str> {
+    let mut fields = Vec::new();
+    if options.field != SearchField::Description {
+        fields.push(cmd);
+        fields.extend(entry.aliases.iter().map(|alias| alias.as_str()));
+    }
+    if options.field != SearchField::Command {
+        fields.push(entry.description.as_str());
     }
+    if options.in_output
+        && let Some(output) = &entry.last_output {
+        fields.push(output.as_str());
+    }
+    fields
+}
 
-    for (cmd, desc) in &store.commands {
-        println!("$ {}{}", cmd.bright_green(), (": ".to_owned() + desc).blue());
-    };
-    Ok(())
+// How well a command matched, best first. Used to order search results.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum MatchRank {
+    // The command text or an alias is exactly the pattern
+    Exact,
+    // The command text or an alias starts with the pattern
+    Prefix,
+    // The pattern is found in the command text or an alias
+    Substring,
+    // Only the description matched, or the keywords were spread over several fields
+    Other,
 }
 
-fn search_commands(pattern: String) -> Result<()> {
-    let path = get_commands_file()?;
-    let store = CommandStore::load(&path)?;
+// Find all commands that match the pattern, best matches first and alphabetically
+// otherwise. Used in List, search and delete commands.
+fn search_logic(pattern: String, store: &CommandStore, options: &SearchOptions) -> Result<Vec<String>> {
+    Ok(search_matches(pattern, store, options)?.into_iter().map(|(cmd, _)| cmd).collect())
+}
 
-    let matching_commands = search_logic(pattern.clone(), &store);
-    if matching_commands.is_empty() {
-        println!("No commands found matching '{}'", pattern);
+// Like `search_logic`, along with whether each command was found only by its description
+// and not by its command text or aliases. Fuzzy matches are never marked.
+fn search_matches(pattern: String, store: &CommandStore, options: &SearchOptions) -> Result<Vec<(String, bool)>> {
+    if options.fuzzy {
+        return Ok(fuzzy_search_logic(&pattern, store, options).into_iter().map(|(cmd, _)| (cmd, false)).collect());
+    }
+    let regex = if options.regex {
+        let source = if options.word { word_regex(&pattern) } else { pattern.clone() };
+        Some(RegexBuilder::new(&source).case_insensitive(!options.case_sensitive).build()
+        .with_context(|| format!("Invalid regular expression: '{}'", pattern))?)
+    } else {
+        None
+    };
+    let normalize = |text: &str| if options.case_sensitive { text.to_string() } else { text.to_lowercase() };
+    let keywords: Vec<String> = pattern.split_whitespace().map(normalize).collect();
+    // Keywords and fields are normalized already, so these can match case exactly
+    let word_matchers: Vec<Regex> = if options.word {
+        keywords.iter().map(|keyword| Regex::new(&word_regex(&regex::escape(keyword))))
+        .collect::<Result<_, _>>().context("Failed to build search pattern")?
     } else {
-        for cmd in matching_commands {
-            println!("$ {}{}", cmd.bright_green(), 
-            (": ".to_owned() + store.commands.get(&cmd).unwrap_or(&String::new())).blue());
+        Vec::new()
+    };
+    let phrase = keywords.join(" ");
+    let mut matching_commands = Vec::new();
+
+    // How many keywords are found in the fields, or whether the regex is
+    let matched_keywords = |fields: &[&str]| match &regex {
+        Some(regex) => usize::from(fields.iter().any(|field| regex.is_match(field))),
+        None => {
+            let fields: Vec<String> = fields.iter().map(|field| normalize(field)).collect();
+            keywords.iter().enumerate()
+            .filter(|(i, keyword)| fields.iter().any(|field| match word_matchers.get(*i) {
+                Some(matcher) => matcher.is_match(field),
+                None => field.contains(keyword.as_str()),
+            }))
+            .count()
+        }
+    };
+    let needed = if regex.is_none() && !(options.any && !keywords.is_empty()) { keywords.len() } else { 1 };
+
+    for (cmd, entry) in &store.commands {
+        if matched_keywords(&search_fields(cmd, entry, options)) < needed {
+            continue;
         }
+
+        // Rank by the command text and aliases, even when only searching descriptions
+        let mut names = vec![cmd.as_str()];
+        names.extend(entry.aliases.iter().map(|alias| alias.as_str()));
+        let description_only = options.field != SearchField::Command
+            && matched_keywords(&names) == 0
+            && matched_keywords(&[entry.description.as_str()]) > 0;
+        let rank = match &regex {
+            Some(regex) => {
+                let found: Vec<regex::Match> = names.iter().filter_map(|name| regex.find(name)).collect();
+                if names.iter().any(|name| regex.find(name).is_some_and(|found| found.len() == name.len())) {
+                    MatchRank::Exact
+                } else if found.iter().any(|found| found.start() == 0) {
+                    MatchRank::Prefix
+                } else if !found.is_empty() {
+                    MatchRank::Substring
+                } else {
+                    MatchRank::Other
+                }
+            }
+            None => {
+                let names: Vec<String> = names.into_iter().map(normalize).collect();
+                if names.iter().any(|name| name.trim() == phrase) {
+                    MatchRank::Exact
+                } else if names.iter().any(|name| name.trim_start().starts_with(&phrase)) {
+                    MatchRank::Prefix
+                } else if names.iter().any(|name| name.contains(&phrase)) {
+                    MatchRank::Substring
+                } else {
+                    MatchRank::Other
+                }
+            }
+        };
+        matching_commands.push((cmd.clone(), rank, description_only));
     }
-    Ok(())
+    matching_commands.sort_by(|a, b| {
+        a.1.cmp(&b.1)
+        .then_with(|| a.0.to_lowercase().cmp(&b.0.to_lowercase()))
+        .then_with(|| a.0.cmp(&b.0))
+    });
+    Ok(matching_commands.into_iter().map(|(cmd, _, description_only)| (cmd, description_only)).collect())
 }
 
-fn delete_command(pattern: String) -> Result<()> {
-    use std::io::{self, BufRead};
-    let path = get_commands_file()?;
-    let mut store = CommandStore::load(&path)?;
+// Require a match to start and end at the edges of the text or next to a character that
+// isn't part of a word, like `grep -w`
+fn word_regex(source: &str) -> String {
+    format!(r"(?:^|\W)(?:{})(?:$|\W)", source)
+}
 
-    let matching_commands = search_logic(pattern.clone(), &store);
-    if matching_commands.is_empty() {
-        println!("No commands found matching '{}'", pattern);
+// Score every command against the pattern, returning the matches best first
+fn fuzzy_search_logic(pattern: &str, store: &CommandStore, options: &SearchOptions) -> Vec<(String, i64)> {
+    let matcher = if options.case_sensitive {
+        SkimMatcherV2::default().respect_case()
     } else {
-        println!("Found {} matching commands:", matching_commands.len());
-        for (i, cmd) in matching_commands.iter().enumerate() {
-            println!("[{}] {}{}", 
-            i + 1, 
-            cmd.bright_green(), 
-            (": ".to_owned() + store.commands.get(cmd).unwrap_or(&String::new())).blue());
-        };
-        print!("Enter a number to delete: ");
-        io::stdout().flush()?;
+        SkimMatcherV2::default().ignore_case()
+    };
+    let pattern: String = pattern.split_whitespace().collect();
 
-        let mut line = String::new();
-        io::stdin().lock().read_line(&mut line)?;
+    let mut matching_commands: Vec<(String, i64)> = store.commands.iter()
+    .filter_map(|(cmd, entry)| {
+        matcher.fuzzy_match(&search_fields(cmd, entry, options).join(" "), &pattern)
+        .map(|score| (cmd.clone(), score))
+    }).collect();
+    matching_commands.sort_by(|a, b| {
+        b.1.cmp(&a.1).then_with(|| a.0.to_lowercase().cmp(&b.0.to_lowercase()))
+    });
+    matching_commands
+}
 
-        if let Ok(choice) = line.trim().parse::<usize>() {
-            if choice <= matching_commands.len() {
-                let cmd_to_delete = &matching_commands[choice - 1];
-                store.commands.remove(cmd_to_delete);
-                store.save(&path)?;
-                println!("Deleted command: {}", cmd_to_delete);
-            }
-        };
-    };
-    Ok(())
+// Sort commands alphabetically, ignoring case, so output is stable between runs
+fn sort_commands<T: AsRef<str>>(commands: &mut [T]) {
+    commands.sort_by(|a, b| {
+        let (a, b) = (a.as_ref(), b.as_ref());
+        a.to_lowercase().cmp(&b.to_lowercase()).then_with(|| a.cmp(b))
+    });
 }
 
-fn edit_commands() -> Result<()> {
-    let path = get_commands_file()?;
-    let mut store = CommandStore::load(&path)?;
+// Color every match of the regex, keeping the base color for the rest of the text
+fn highlight_matches(text: &str, regex: &Regex, base: fn(&str) -> ColoredString) -> String {
+    let mut highlighted = String::new();
+    let mut last = 0;
+    for found in regex.find_iter(text).filter(|found| !found.is_empty()) {
+        highlighted.push_str(&base(&text[last..found.start()]).to_string());
+        highlighted.push_str(&found.as_str().yellow().underline().to_string());
+        last = found.end();
+    }
+    highlighted.push_str(&base(&text[last..]).to_string());
+    highlighted
+}
 
-    // Create and write commands a temporary file
-    let mut temp_file = NamedTempFile::new().context("Failed to create temporary file")?;
-    for (cmd, desc) in &store.commands {
-        writeln!(temp_file, "{}:::{}", cmd, desc).context("Failed to write to temp file")?;
+// Build a regex locating what a search pattern matched, for highlighting
+fn highlight_regex(pattern: &str, options: &SearchOptions) -> Option<Regex> {
+    if options.fuzzy {
+        return None;
     }
-    let temp_path = temp_file.path().to_owned();
-    temp_file.flush().context("Failed to flush temp file")?;
-    let editor = std::env::var("EDITOR").unwrap_or_else(|_| "nano".to_string());
-    let status = Command::new(&editor)
-    .arg(&temp_path)
-    .status()
-    .context(format!("Failed to open editor: {}", editor))?;
+    let source = if options.regex {
+        pattern.to_string()
+    } else {
+        pattern.split_whitespace().map(regex::escape).collect::<Vec<_>>().join("|")
+    };
+    if source.is_empty() {
+        return None;
+    }
+    let source = if options.word { format!(r"\b(?:{})\b", source) } else { source };
+    RegexBuilder::new(&source).case_insensitive(!options.case_sensitive).build().ok()
+}
 
-    if !status.success() {
-        return Err(anyhow::anyhow!("Editor exited with non-zero status"));
+// Print a saved command with its description, followed by its tags, each in its own color.
+// Text matching `highlight` is marked so it's clear why a search result matched.
+fn print_command(cmd: &str, entry: &CommandEntry, highlight: Option<&Regex>, output: &OutputOptions) {
+    println!("{}", format_command(cmd, entry, highlight, output));
+}
+
+fn format_command(cmd: &str, entry: &CommandEntry, highlight: Option<&Regex>, output: &OutputOptions) -> String {
+    let masked = if output.reveal { cmd.to_string() } else { mask_secrets(cmd) };
+    let cmd = masked.as_str();
+    if output.plain {
+        return format!("{}\t{}", cmd, entry.description.replace('\n', " "));
     }
-    let mut content = String::new();
-    std::io::Read::read_to_string(
-        &mut File::open(&temp_path).context("Failed to open temporary file after editing")?,
-        &mut content
-    ).context("Failed to read temporary file after editing")?;
-    let mut new_commands = HashMap::new();
-    for line in content.lines() {
-        if let Some((cmd, desc)) = line.split_once(":::") {
-            new_commands.insert(cmd.trim().to_string(), desc.trim().to_string());
-        }
+    // Replaced in one pass, so a `{desc}` inside a command stays as it is
+    if let Some(template) = &output.template {
+        let token = Regex::new(r"\{(cmd|desc|tags|uses)\}").expect("valid regex");
+        return token.replace_all(template, |caps: &regex::Captures| match &caps[1] {
+            "cmd" => cmd.to_string(),
+            "desc" => entry.description.replace('\n', " "),
+            "tags" => entry.tags.join(", "),
+            _ => entry.use_count.to_string(),
+        }).into_owned();
     }
-    store.commands = new_commands;
-    store.save(&path)?;
+    // Continuation lines of a multi-line description are indented under the command
+    let description = ": ".to_owned() + &entry.description.replace('\n', "\n    ");
+    let mut line = match highlight {
+        Some(regex) => format!("$ {}{}",
+        highlight_matches(cmd, regex, |text| text.bright_green()),
+        highlight_matches(&description, regex, |text| text.blue())),
+        None => format!("$ {}{}", cmd.bright_green(), description.blue()),
+    };
+    if !entry.tags.is_empty() {
+        let tags: Vec<String> = entry.tags.iter().map(|tag| tag.color(tag_color(tag)).to_string()).collect();
+        line.push_str(&format!(" [{}]", tags.join(", ")));
+    }
+    line
+}
 
-    println!("Commands updated.");
-    Ok(())
+// Colors for tags, leaving out the green and blue of commands and descriptions
+const TAG_COLORS: [colored::Color; 8] = [
+    colored::Color::Cyan,
+    colored::Color::Magenta,
+    colored::Color::Yellow,
+    colored::Color::Red,
+    colored::Color::BrightCyan,
+    colored::Color::BrightMagenta,
+    colored::Color::BrightYellow,
+    colored::Color::BrightRed,
+];
+
+// The same tag always gets the same color. FNV-1a instead of the std hasher, whose
+// output may change between Rust releases.
+fn tag_color(tag: &str) -> colored::Color {
+    let hash = tag.to_lowercase().bytes()
+    .fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3));
+    TAG_COLORS[(hash % TAG_COLORS.len() as u64) as usize]
 }
 
-fn execute_command(pattern: String) -> Result<()> {
-    use std::io::{self, BufRead};
-    let path = get_commands_file()?;
-    let store = CommandStore::load(&path)?;
+fn new_command(path: &Path, args: NewArgs) -> Result<()> {
+    let mut store = CommandStore::load(path)?;
 
-    let matching_commands = search_logic(pattern.clone(), &store);
-    if matching_commands.is_empty() {
-        println!("No commands found matching '{}'", pattern);
-    } else {
-        println!("Found {} matching commands:", matching_commands.len());
-        for (i, cmd) in matching_commands.iter().enumerate() {
-            println!("[{}] {}{}", 
-            i + 1, 
-            cmd.bright_green(), 
-            (": ".to_owned() + store.commands.get(cmd).unwrap_or(&String::new())).blue());
-        };
-        print!("Enter a number to execute: ");
-        io::stdout().flush()?;
-
-        let mut line = String::new();
-        io::stdin().lock().read_line(&mut line)?;
-
-        if let Ok(choice) = line.trim().parse::<usize>() {
-            if choice <= matching_commands.len() {
-                let cmd_to_execute = &matching_commands[choice - 1];
-                println!("Executing: {}", cmd_to_execute);
-                let (shell, shell_arg) = if cfg!(target_os = "windows") {
-                    ("cmd", "/C")
-                } else {
-                    ("sh", "-c")
-                };
-                Command::new(shell)
-                .arg(shell_arg)
-                .arg(&cmd_to_execute)
-                .stdin(Stdio::inherit())
-                .stdout(Stdio::inherit())
-                .stderr(Stdio::inherit())
-                .status()
-                .context(format!("Failed to execute: {}", cmd_to_execute))?;
-            }
-        }
-    };
-    Ok(())
-}
-
-fn main() -> Result<()> {
-    let mut commands = Vec::new();
-    let cli_command = Cli::command();
-    for subcommand in cli_command.get_subcommands() {
-        commands.push(subcommand.get_name());
-    }
-    commands.extend_from_slice(&["help", "--help", "-h"]);
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() > 1 { //search saved commands
-        if !commands.contains(&args[1].as_str()) {
-            let store = CommandStore::load(&get_commands_file()?)?;
-            let matching_commands = search_logic((args[1..].join(" ")).clone(), &store);
-            if !matching_commands.is_empty() {
-                return Ok(for cmd in matching_commands {
-                    println!("$ {}{}",
-                    cmd.bright_green(), 
-                    (": ".to_owned() + store.commands.get(&cmd).unwrap_or(&String::new())).blue());
-                });
-            }
-        }
-    }
-    match Cli::parse().command {
-        Some(Commands::New { command, description })
-        | Some(Commands::Add { command, description }) => new_command(command, description),
-        Some(Commands::List)
-        | Some(Commands::Ls) => list_commands(),
-        Some(Commands::Grep { pattern })
-        | Some(Commands::Find { pattern })
-        | Some(Commands::Search { pattern }) => search_commands(pattern),
-        Some(Commands::Remove { pattern })
-        | Some(Commands::Rm { pattern })
-        | Some(Commands::Delete { pattern }) => delete_command(pattern),
-        Some(Commands::Edit) => edit_commands(),
-        Some(Commands::Run { pattern })
-        | Some(Commands::Execute { pattern }) => execute_command(pattern),
+    // Refuse to guess where an unquoted command ends and its description begins
+    if !args.extra.is_empty() {
+        let received: Vec<String> = args.command.iter().chain(&args.description).chain(&args.extra)
+        .map(|arg| format!("'{}'", arg))
+        .collect();
+        return Err(anyhow::anyhow!(
+            "Expected a command and a description, got {} arguments: {}\n\
+            Quote the command and the description, e.g. keepc new \"git log --oneline\" \"pretty log\",\n\
+            or put the command after --, e.g. keepc new -- git log --oneline",
+            received.len(), received.join(" ")));
+    }
+
+    // Get command from user, after the description with --desc-first
+    let mut description = None;
+    let command = match args.command {
+        None if !args.words.is_empty() => args.words.join(" "),
+        Some(cmd) => cmd.trim().to_string(),
+        None if args.stdin => read_command_from_stdin()?,
         None => {
-            Cli::parse_from(["keepc", "--help"]);
-            Ok(())
+            if args.desc_first {
+                description = Some(prompt_multiline("Enter description (optional, end with an empty line): ")?);
+            }
+            prompt_edit("Enter command: ", "")?
+        }
+    };
+    if command.is_empty() {
+        return Err(anyhow::anyhow!("Command cannot be empty"));
+    }
+    if let Some(existing) = store.commands.get(&command)
+        && !args.force
+        && !confirm(&format!("Command already exists with description '{}'. Overwrite?", existing.description))? {
+        println!("Aborted.");
+        return Ok(());
+    }
+
+    // Get description from user if provided. Stdin is used up by the command in --stdin
+    // mode, so ask on the terminal instead.
+    let description = match description.or(args.description) {
+        Some(desc) => desc,
+        None if args.stdin => prompt_tty("Enter description (optional): ")?,
+        None => prompt_multiline("Enter description (optional, end with an empty line): ")?,
+    };
+    let cwd = args.cwd.map(|cwd| cwd.trim().to_string()).filter(|cwd| !cwd.is_empty());
+    let mut entry = CommandEntry {
+        description,
+        notes: args.note.filter(|note| !note.trim().is_empty()),
+        cwd,
+        env: args.env.into_iter().collect(),
+        dangerous: args.dangerous,
+        created_at: Some(Utc::now()),
+        ..Default::default()
+    };
+    for tag in args.tags {
+        let tag = tag.trim().to_string();
+        if !tag.is_empty() && !entry.tags.contains(&tag) {
+            entry.tags.push(tag);
+        }
+    }
+    for alias in args.aliases {
+        let alias = alias.trim().to_string();
+        if !alias.is_empty() && !entry.aliases.contains(&alias) {
+            entry.aliases.push(alias);
+        }
+    }
+    if store.commands.insert(command.clone(), entry).is_some() {
+        store.save_with_backup(path)?;
+    } else {
+        store.save(path)?;
+    }
+    info!("Saved: {}", command);
+    if !args.no_check
+        && let Some(program) = missing_program(&command) {
+        eprintln!("{} '{}' not found in PATH — saved anyway.", "Note:".yellow(), program);
+    }
+    Ok(())
+}
+
+// Shell builtins and keywords, which aren't programs in PATH
+const SHELL_BUILTINS: &[&str] = &[
+    ".", ":", "[", "[[", "!", "alias", "bg", "builtin", "case", "cd", "command", "declare", "echo", "eval",
+    "exec", "exit", "export", "false", "fg", "for", "function", "history", "if", "jobs", "kill", "let",
+    "local", "printf", "pwd", "read", "set", "shift", "source", "test", "time", "trap", "true", "type",
+    "ulimit", "umask", "unset", "until", "wait", "while",
+];
+
+// The program a command starts with, if it can't be found in PATH. Commands starting
+// with a builtin, a variable, a path or anything else the shell resolves are left alone.
+fn missing_program(command: &str) -> Option<String> {
+    // Skip `KEY=value` assignments in front of the program
+    let program = command.split_whitespace().find(|word| !word.contains('='))?;
+    if SHELL_BUILTINS.contains(&program)
+        || program.contains(['/', '\\', '$', '`', '{', '(', '~', '\'', '"']) {
+        return None;
+    }
+    find_in_path(program).is_none().then(|| program.to_string())
+}
+
+// Parse a date like `2024-01-01`, taken as local midnight, or a time ago like `30d`, `2w`,
+// `6m` or `1y`
+fn parse_time(value: &str) -> Result<DateTime<Utc>, String> {
+    let value = value.trim();
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return date.and_hms_opt(0, 0, 0)
+        .and_then(|midnight| midnight.and_local_timezone(Local).earliest())
+        .map(|time| time.with_timezone(&Utc))
+        .ok_or_else(|| format!("'{}' is not a valid local date", value));
+    }
+    let unit_days = match value.chars().last() {
+        Some('d') => 1,
+        Some('w') => 7,
+        Some('m') => 30,
+        Some('y') => 365,
+        _ => return Err(format!("expected a date like 2024-01-01 or a time ago like 30d, 2w, 6m or 1y, got '{}'", value)),
+    };
+    let count: i64 = value[..value.len() - 1].parse().map_err(|_| format!("'{}' is not a number of days, weeks, months or years", value))?;
+    chrono::Duration::try_days(count.saturating_mul(unit_days))
+    .and_then(|ago| Utc::now().checked_sub_signed(ago))
+    .ok_or_else(|| format!("'{}' is too far back", value))
+}
+
+// Split a `KEY=VALUE` argument of `--env`
+fn parse_env_var(value: &str) -> Result<(String, String), String> {
+    let (key, value) = value.split_once('=').ok_or_else(|| format!("expected KEY=VALUE, got '{}'", value))?;
+    let key = key.trim();
+    if key.is_empty() {
+        return Err("the variable name cannot be empty".to_string());
+    }
+    // The name ends up in an `export` of the shell
+    if key.starts_with(|c: char| c.is_ascii_digit()) || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!("'{}' is not a valid variable name", key));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+fn save_last_command(path: &Path) -> Result<()> {
+    let command = last_history_command()?;
+    println!("Last command: {}", command.bright_green());
+    new_command(path, NewArgs { command: Some(command), ..Default::default() })
+}
+
+// Find the shell history file: $HISTFILE, else the history of the current shell,
+// else whichever of bash or zsh history exists
+fn history_file() -> Result<PathBuf> {
+    if let Some(histfile) = std::env::var_os("HISTFILE").filter(|value| !value.is_empty()) {
+        return Ok(PathBuf::from(histfile));
+    }
+    let home = dirs::home_dir().context("Could not determine home directory")?;
+    let mut candidates = vec![home.join(".bash_history"), home.join(".zsh_history")];
+    if std::env::var("SHELL").is_ok_and(|shell| shell.ends_with("zsh")) {
+        candidates.reverse();
+    }
+    candidates.into_iter().find(|candidate| candidate.exists())
+    .context("No shell history file found. Set $HISTFILE to your history file")
+}
+
+// Read the most recent command from the shell history, skipping keepc itself
+fn last_history_command() -> Result<String> {
+    let file = history_file()?;
+    let bytes = fs::read(&file).with_context(|| format!("Failed to read history file {}", file.display()))?;
+    String::from_utf8_lossy(&bytes).lines().rev()
+    .map(parse_history_line)
+    .find(|command| !command.is_empty() && command.split_whitespace().next() != Some("keepc"))
+    .with_context(|| format!("No commands found in {}", file.display()))
+}
+
+// Strip the `: <timestamp>:<duration>;` prefix zsh adds in extended history mode
+fn parse_history_line(line: &str) -> String {
+    if let Some(rest) = line.strip_prefix(": ")
+        && let Some((_, command)) = rest.split_once(';') {
+        return command.trim().to_string();
+    }
+    line.trim().to_string()
+}
+
+// Read a piped command, dropping the entry number `history` puts in front of it
+fn read_command_from_stdin() -> Result<String> {
+    let mut input = String::new();
+    std::io::Read::read_to_string(&mut std::io::stdin(), &mut input).context("Failed to read stdin")?;
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(anyhow::anyhow!("No command received on stdin"));
+    }
+    let without_number = input.trim_start_matches(|c: char| c.is_ascii_digit());
+    if without_number.len() < input.len() && without_number.starts_with(['*', ' ', '\t']) {
+        return Ok(without_number.trim_start_matches('*').trim().to_string());
+    }
+    Ok(input.to_string())
+}
+
+// Shape of each command in `list --json` output
+#[derive(Serialize)]
+struct JsonCommand<'a> {
+    command: &'a str,
+    description: &'a str,
+}
+
+// Format an optional timestamp in local time
+fn format_timestamp(timestamp: Option<DateTime<Utc>>, missing: &str) -> String {
+    match timestamp {
+        Some(timestamp) => timestamp.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string(),
+        None => missing.to_string(),
+    }
+}
+
+// Describe how the last run of a command went, e.g. `succeeded in 1.2s`
+fn format_last_run(entry: &CommandEntry) -> String {
+    let result = match entry.last_exit {
+        Some(0) => "succeeded".green(),
+        Some(code) => format!("failed with exit code {}", code).red(),
+        None if entry.last_duration_ms.is_some() => "was killed".red(),
+        None => "result unknown".dimmed(),
+    };
+    match entry.last_duration_ms {
+        Some(ms) => format!("{} in {:.1}s", result, ms as f64 / 1000.0),
+        None => result.to_string(),
+    }
+}
+
+// All commands in the order `list` shows them: sorted, then pinned ones first.
+// `run --from-list` indexes into this in the order of config.toml, which is what
+// `list --numbered` shows, since it can't be combined with --sort or any filter.
+fn listed_commands(store: &CommandStore, sort: SortOrder) -> Vec<&String> {
+    let mut commands: Vec<&String> = store.commands.keys().collect();
+    sort_commands(&mut commands);
+    match sort {
+        SortOrder::Name => {}
+        SortOrder::Uses => commands.sort_by_key(|cmd| std::cmp::Reverse(store.commands[*cmd].use_count)),
+        SortOrder::Recent => commands.sort_by_key(|cmd| std::cmp::Reverse(store.commands[*cmd].last_used)),
+    }
+    // Pinned commands come first, in the same order among themselves
+    commands.sort_by_key(|cmd| !store.commands[*cmd].pinned);
+    commands
+}
+
+fn list_commands(path: &Path, args: ListArgs, output: &OutputOptions, config: &Config) -> Result<()> {
+    let store = CommandStore::load(path)?;
+
+    if args.json {
+        colored::control::set_override(false);
+    } else if store.commands.is_empty() {
+        println!("No commands saved.");
+        return Ok(());
+    }
+
+    // Every requested tag has to be present on the command
+    let tags: Vec<String> = args.tags.iter().map(|tag| tag.to_lowercase()).collect();
+    let listed = match args.order {
+        Some(AddedOrder::Added) => store.commands.keys().collect(),
+        Some(AddedOrder::Recent) => store.commands.keys().rev().collect(),
+        None => listed_commands(&store, args.sort.unwrap_or(config.sort)),
+    };
+    // Commands without the date a filter looks at only pass with --include-undated
+    let since = args.since;
+    let before = args.last_used_before;
+    let mut matching_commands: Vec<&String> = listed.into_iter()
+    .filter(|cmd| {
+        let entry = &store.commands[*cmd];
+        tags.iter().all(|tag| entry.tags.iter().any(|t| t.to_lowercase() == *tag))
+        && since.is_none_or(|since| entry.created_at.map_or(args.include_undated, |created| created >= since))
+        && before.is_none_or(|before| entry.last_used.map_or(args.include_undated, |used| used < before))
+    }).collect();
+    let hidden = truncate_results(&mut matching_commands, args.limit);
+    if args.json {
+        let json_commands: Vec<JsonCommand> = matching_commands.iter()
+        .map(|cmd| JsonCommand { command: cmd, description: &store.commands[*cmd].description })
+        .collect();
+        serde_json::to_writer_pretty(std::io::stdout(), &json_commands).context("Failed to write JSON")?;
+        println!();
+        return Ok(());
+    }
+    if matching_commands.is_empty() {
+        // --limit 0 hides everything that matched
+        if hidden > 0 {
+            print_hidden_count(hidden);
+            return Ok(());
+        }
+        let mut filters = Vec::new();
+        if !tags.is_empty() {
+            filters.push(format!("with tag '{}'", tags.join("', '")));
+        }
+        if let Some(since) = since {
+            filters.push(format!("saved since {}", format_timestamp(Some(since), "")));
+        }
+        if let Some(before) = before {
+            filters.push(format!("last run before {}", format_timestamp(Some(before), "")));
+        }
+        println!("No commands {}.", filters.join(" and "));
+        return Ok(());
+    }
+
+    // Collect the output first so it can go through a pager
+    let mut lines = Vec::new();
+    let push_command = |lines: &mut Vec<String>, i: usize, cmd: &str| {
+        let entry = &store.commands[cmd];
+        let mut marker = if args.numbered { format!("[{}] ", i + 1) } else { String::new() };
+        if entry.pinned && !output.plain {
+            marker.push_str(&format!("{} ", "★".yellow()));
+        }
+        let mut line = marker + &format_command(cmd, entry, None, output);
+        if args.show_placeholders {
+            let names: Vec<String> = find_placeholders(cmd).iter()
+            .map(|name| name.strip_prefix(SECRET_PREFIX).unwrap_or(name).trim().to_string())
+            .collect();
+            if !names.is_empty() {
+                line.push_str(&format!(" [needs: {}]", names.join(", ")).yellow().to_string());
+            }
+        }
+        lines.push(line);
+        if args.verbose {
+            lines.push(format!("    {}", format!("created: {}, last used: {}",
+            format_timestamp(entry.created_at, "unknown"),
+            format_timestamp(entry.last_used, "never")).dimmed()));
+            if entry.last_used.is_some() {
+                lines.push(format!("    {}", format_last_run(entry)));
+            }
+        }
+    };
+    if args.group_by_tag {
+        // A command with several tags is listed under each of them. Numbers stay those
+        // of the unfiltered flat list, so they still work with `run --from-list`.
+        let mut groups: HashMap<&str, Vec<(usize, &String)>> = HashMap::new();
+        let mut untagged = Vec::new();
+        for (i, cmd) in matching_commands.iter().enumerate() {
+            let entry = &store.commands[*cmd];
+            if entry.tags.is_empty() {
+                untagged.push((i, *cmd));
+            }
+            for tag in &entry.tags {
+                groups.entry(tag.as_str()).or_default().push((i, *cmd));
+            }
+        }
+        let mut names: Vec<&str> = groups.keys().copied().collect();
+        sort_commands(&mut names);
+        let sections = names.into_iter().map(|name| (name, &groups[name]))
+        .chain(Some(("(untagged)", &untagged)).filter(|(_, commands)| !commands.is_empty()));
+        for (name, commands) in sections {
+            if !lines.is_empty() {
+                lines.push(String::new());
+            }
+            lines.push(format!("# {}", name).bold().to_string());
+            for (i, cmd) in commands {
+                push_command(&mut lines, *i, cmd);
+            }
+        }
+    } else {
+        for (i, cmd) in matching_commands.into_iter().enumerate() {
+            push_command(&mut lines, i, cmd);
+        }
+    }
+    if hidden > 0 {
+        lines.push(format!("... and {} more", hidden).dimmed().to_string());
+    }
+    print_paged(&lines.join("\n"), !args.no_pager)
+}
+
+// Print the text, through $PAGER (or `less -R`) when it doesn't fit on the terminal
+fn print_paged(text: &str, use_pager: bool) -> Result<()> {
+    let term = console::Term::stdout();
+    let fits = match term.size_checked() {
+        Some((rows, _)) => text.lines().count() < usize::from(rows),
+        None => true,
+    };
+    if !use_pager || fits || !std::io::stdout().is_terminal() {
+        println!("{}", text);
+        return Ok(());
+    }
+
+    let pager = std::env::var("PAGER").ok().filter(|value| !value.trim().is_empty())
+    .unwrap_or_else(|| "less -R".to_string());
+    let parts: Vec<&str> = pager.split_whitespace().collect();
+    let Ok(mut child) = Command::new(parts[0]).args(&parts[1..]).stdin(Stdio::piped()).spawn() else {
+        println!("{}", text);
+        return Ok(());
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // The pager closing early, e.g. quitting less, is not an error
+        let _ = writeln!(stdin, "{}", text);
+    }
+    child.wait().context("Failed to wait for pager")?;
+    Ok(())
+}
+
+fn top_commands(path: &Path, output: &OutputOptions) -> Result<()> {
+    let store = CommandStore::load(path)?;
+
+    if store.commands.is_empty() {
+        println!("No commands saved.");
+        return Ok(());
+    }
+
+    let mut commands: Vec<&String> = store.commands.keys().collect();
+    sort_commands(&mut commands);
+    commands.sort_by(|a, b| store.commands[*b].use_count.cmp(&store.commands[*a].use_count));
+    for cmd in commands {
+        let entry = &store.commands[cmd];
+        print!("{} ", format!("({})", entry.use_count).dimmed());
+        print_command(cmd, entry, None, output);
+    }
+    Ok(())
+}
+
+fn recent_commands(path: &Path, limit: usize, output: &OutputOptions) -> Result<()> {
+    let store = CommandStore::load(path)?;
+
+    if store.commands.is_empty() {
+        println!("No commands saved.");
+        return Ok(());
+    }
+
+    // Commands that were never run have no timestamp and sort last
+    let mut commands: Vec<&String> = store.commands.keys().collect();
+    sort_commands(&mut commands);
+    commands.sort_by_key(|cmd| std::cmp::Reverse(store.commands[*cmd].last_used));
+    let hidden = truncate_results(&mut commands, Some(limit));
+    for cmd in commands {
+        let entry = &store.commands[cmd];
+        print!("{} ", format!("({})", format_timestamp(entry.last_used, "never")).dimmed());
+        print_command(cmd, entry, None, output);
+    }
+    print_hidden_count(hidden);
+    Ok(())
+}
+
+fn count_commands(path: &Path, by_tag: bool) -> Result<()> {
+    let store = CommandStore::load(path)?;
+    println!("{}", store.commands.len());
+
+    if by_tag {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for entry in store.commands.values() {
+            for tag in &entry.tags {
+                *counts.entry(tag.as_str()).or_default() += 1;
+            }
+        }
+        let mut tags: Vec<&str> = counts.keys().copied().collect();
+        sort_commands(&mut tags);
+        for tag in tags {
+            println!("{}: {}", tag, counts[tag]);
+        }
+    }
+    Ok(())
+}
+
+fn print_stats(path: &Path, output: &OutputOptions) -> Result<()> {
+    let store = CommandStore::load(path)?;
+    let total = store.commands.len();
+    println!("{} {}", "Commands:           ".bold(), total);
+    if total == 0 {
+        return Ok(());
+    }
+
+    let descriptions: Vec<usize> = store.commands.values()
+    .map(|entry| entry.description.trim().chars().count())
+    .filter(|length| *length > 0)
+    .collect();
+    let tagged = store.commands.values().filter(|entry| !entry.tags.is_empty()).count();
+    println!("{} {}", "With description:   ".bold(), descriptions.len());
+    println!("{} {}", "Without description:".bold(), total - descriptions.len());
+    println!("{} {}", "With tags:          ".bold(), tagged);
+    if !descriptions.is_empty() {
+        let average = descriptions.iter().sum::<usize>() as f64 / descriptions.len() as f64;
+        println!("{} {:.1} characters", "Average description:".bold(), average);
+    }
+
+    let shown = |cmd: &str| if output.reveal { cmd.to_string() } else { mask_secrets(cmd) };
+    if let Some((cmd, entry)) = store.commands.iter().filter(|(_, entry)| entry.use_count > 0).max_by_key(|(_, entry)| entry.use_count) {
+        println!("{} {} {}", "Most used:          ".bold(), shown(cmd).bright_green(), format!("({} runs)", entry.use_count).dimmed());
+    }
+    // Commands saved before keepc recorded creation times have no timestamp to compare
+    let dated = || store.commands.iter().filter_map(|(cmd, entry)| entry.created_at.map(|created| (cmd, created)));
+    if let Some((cmd, created)) = dated().min_by_key(|(_, created)| *created) {
+        println!("{} {} {}", "Oldest:             ".bold(), shown(cmd).bright_green(), format!("({})", format_timestamp(Some(created), "")).dimmed());
+    }
+    if let Some((cmd, created)) = dated().max_by_key(|(_, created)| *created) {
+        println!("{} {} {}", "Newest:             ".bold(), shown(cmd).bright_green(), format!("({})", format_timestamp(Some(created), "")).dimmed());
+    }
+    Ok(())
+}
+
+fn search_commands(path: &Path, args: GrepArgs, output: &OutputOptions) -> Result<()> {
+    let store = CommandStore::load(path)?;
+
+    let mut matching_commands = search_matches(args.pattern.clone(), &store, &args.options)?;
+    if matching_commands.is_empty() {
+        info!("No commands found matching '{}'", args.pattern);
+        return Err(NoMatch.into());
+    } else {
+        let highlight = if colored::control::SHOULD_COLORIZE.should_colorize() {
+            highlight_regex(&args.pattern, &args.options)
+        } else {
+            None
+        };
+        let hidden = truncate_results(&mut matching_commands, args.limit);
+        // Mark results whose command doesn't visibly contain the pattern
+        for (cmd, description_only) in matching_commands {
+            let line = format_command(&cmd, &store.commands[&cmd], highlight.as_ref(), output);
+            if description_only && !output.plain && output.template.is_none() {
+                println!("{} {}", line, "(desc)".dimmed());
+            } else {
+                println!("{}", line);
+            }
+        }
+        print_hidden_count(hidden);
+    }
+    Ok(())
+}
+
+// Keep the first `limit` results, returning how many were dropped
+fn truncate_results<T>(results: &mut Vec<T>, limit: Option<usize>) -> usize {
+    let Some(limit) = limit.filter(|limit| *limit < results.len()) else {
+        return 0;
+    };
+    let hidden = results.len() - limit;
+    results.truncate(limit);
+    hidden
+}
+
+fn print_hidden_count(hidden: usize) {
+    if hidden > 0 {
+        println!("{}", format!("... and {} more", hidden).dimmed());
+    }
+}
+
+fn export_commands(path: &Path, format: ExportFormat) -> Result<()> {
+    let store = CommandStore::load(path)?;
+    let mut stdout = std::io::stdout().lock();
+
+    match format {
+        ExportFormat::Json => {
+            serde_json::to_writer_pretty(&mut stdout, &store).context("Failed to write commands")?;
+            writeln!(stdout)?;
+        }
+        ExportFormat::Yaml => {
+            serde_norway::to_writer(&mut stdout, &store).context("Failed to write commands")?;
+        }
+        ExportFormat::Sh => {
+            // Each command is preceded by its description as a comment
+            writeln!(stdout, "#!/bin/sh")?;
+            writeln!(stdout, "# Commands exported from keepc")?;
+            let mut commands: Vec<&String> = store.commands.keys().collect();
+            sort_commands(&mut commands);
+            for cmd in commands {
+                writeln!(stdout)?;
+                for line in store.commands[cmd].description.lines() {
+                    writeln!(stdout, "# {}", line)?;
+                }
+                writeln!(stdout, "{}", cmd)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn import_commands(path: &Path, file: Option<PathBuf>, dir: Option<PathBuf>, mode: ImportMode) -> Result<()> {
+    let mut store = CommandStore::load(path)?;
+    let imported = match (file, dir) {
+        (_, Some(dir)) => read_snippets(&dir)?,
+        (Some(file), None) => CommandStore::read_other(&file)?,
+        (None, None) => return Err(anyhow::anyhow!("Give a file or --dir to import from")),
+    };
+
+    let (mut added, mut skipped, mut overwritten) = (0, 0, 0);
+    let mut commands: Vec<(String, CommandEntry)> = imported.commands.into_iter().collect();
+    commands.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut sanitized = Vec::new();
+    for (cmd, mut entry) in commands {
+        let (cmd, cmd_changed) = strip_control_chars(&cmd);
+        if sanitize_entry(&mut entry) || cmd_changed {
+            sanitized.push(cmd.clone());
+        }
+        if cmd.trim().is_empty() {
+            skipped += 1;
+            continue;
+        }
+        if !store.commands.contains_key(&cmd) {
+            store.commands.insert(cmd, entry);
+            added += 1;
+            continue;
+        }
+        match mode {
+            ImportMode::Skip => skipped += 1,
+            ImportMode::Overwrite => {
+                store.commands.insert(cmd, entry);
+                overwritten += 1;
+            }
+            ImportMode::Rename => {
+                let renamed = unique_key(&store, &cmd, "imported");
+                info!("Renamed: {} -> {}", cmd, renamed);
+                store.commands.insert(renamed, entry);
+                added += 1;
+            }
+        }
+    }
+    print_sanitized(&sanitized);
+    store.save_with_backup(path)?;
+    info!("Imported {}, skipped {}, overwritten {}", added, skipped, overwritten);
+    Ok(())
+}
+
+// A free key for a second copy of a command, e.g. `make # imported 2`. The trailing
+// comment keeps the copy runnable in POSIX shells and PowerShell only, cmd passes it on as
+// arguments.
+fn unique_key(store: &CommandStore, cmd: &str, label: &str) -> String {
+    let mut suffix = 1;
+    let mut key = format!("{} # {}", cmd, label);
+    while store.commands.contains_key(&key) {
+        suffix += 1;
+        key = format!("{} # {} {}", cmd, label, suffix);
+    }
+    key
+}
+
+// Report the commands only in this store, only in the other and in both with different
+// descriptions, then add the other's commands and ask which description to keep for each
+// conflict
+fn merge_stores(path: &Path, file: PathBuf) -> Result<()> {
+    let mut store = CommandStore::load(path)?;
+    let theirs = CommandStore::read_other(&file)?;
+
+    let only_mine: Vec<&String> = store.commands.keys().filter(|cmd| !theirs.commands.contains_key(*cmd)).collect();
+    let only_theirs: Vec<&String> = theirs.commands.keys().filter(|cmd| !store.commands.contains_key(*cmd)).collect();
+    let conflicts: Vec<&String> = theirs.commands.iter()
+    .filter(|(cmd, entry)| store.commands.get(*cmd).is_some_and(|mine| mine.description.trim() != entry.description.trim()))
+    .map(|(cmd, _)| cmd)
+    .collect();
+    let sections = [("Only in mine", &only_mine), ("Only in theirs", &only_theirs), ("Different descriptions", &conflicts)];
+    for (title, commands) in sections {
+        println!("{} {}", format!("{}:", title).bold(), commands.len());
+        for cmd in commands.iter() {
+            println!("  {}", mask_secrets(cmd).bright_green());
+        }
+    }
+    if only_theirs.is_empty() && conflicts.is_empty() {
+        info!("Nothing to merge.");
+        return Ok(());
+    }
+
+    for cmd in &only_theirs {
+        store.commands.insert(cmd.to_string(), theirs.commands[*cmd].clone());
+    }
+    let (mut kept, mut replaced, mut both) = (0, 0, 0);
+    for cmd in &conflicts {
+        println!();
+        println!("$ {}", mask_secrets(cmd).bright_green());
+        println!("  mine:   {}", store.commands[*cmd].description.replace('\n', "\n          ").blue());
+        println!("  theirs: {}", theirs.commands[*cmd].description.replace('\n', "\n          ").blue());
+        let choice = loop {
+            match prompt("Keep [m]ine, [t]heirs or [b]oth? [m] ")?.to_lowercase().as_str() {
+                "" | "m" | "mine" => break 'm',
+                "t" | "theirs" => break 't',
+                "b" | "both" => break 'b',
+                _ => continue,
+            }
+        };
+        match choice {
+            't' => {
+                store.commands.insert(cmd.to_string(), theirs.commands[*cmd].clone());
+                replaced += 1;
+            }
+            'b' => {
+                let renamed = unique_key(&store, cmd, "theirs");
+                store.commands.insert(renamed, theirs.commands[*cmd].clone());
+                both += 1;
+            }
+            _ => kept += 1,
+        }
+    }
+    store.save_with_backup(path)?;
+    info!("Added {}, kept mine {}, took theirs {}, kept both {}", only_theirs.len(), kept, replaced, both);
+    Ok(())
+}
+
+// Turn each `*.sh` file of a directory into a command. The first comment is the
+// description and the file name an alias. A one-line snippet without a shebang is saved
+// as it is, any other as a command running the script where it is.
+fn read_snippets(dir: &Path) -> Result<CommandStore> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))?
+    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+    .filter(|file| file.is_file() && file.extension().is_some_and(|ext| ext == "sh"))
+    .collect();
+    files.sort();
+
+    let mut store = CommandStore::new();
+    for file in files {
+        let content = fs::read_to_string(&file).with_context(|| format!("Failed to read {}", file.display()))?;
+        let description = content.lines()
+        .map(str::trim)
+        .find(|line| line.starts_with('#') && !line.starts_with("#!"))
+        .map(|line| line.trim_start_matches('#').trim().to_string())
+        .unwrap_or_default();
+        let lines: Vec<&str> = content.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+        let shebang = content.lines().next()
+        .and_then(|line| line.strip_prefix("#!"))
+        .map(str::trim)
+        .filter(|interpreter| !interpreter.is_empty());
+        let command = match (lines.as_slice(), shebang) {
+            ([], _) => {
+                eprintln!("{} {} has no command, skipped.", "Warning:".yellow(), file.display());
+                continue;
+            }
+            ([line], None) => line.to_string(),
+            _ => script_command(&file, shebang),
+        };
+        let aliases = file.file_stem().map(|stem| stem.to_string_lossy().to_string()).into_iter().collect();
+        store.commands.insert(command, CommandEntry { description, aliases, created_at: Some(Utc::now()), ..Default::default() });
+    }
+    Ok(store)
+}
+
+// Run a snippet file directly when it is executable, else with the interpreter of its
+// shebang, else with sh
+fn script_command(file: &Path, shebang: Option<&str>) -> String {
+    let script = file.canonicalize().unwrap_or_else(|_| file.to_path_buf());
+    let quoted = shell_quote(&script.to_string_lossy());
+    match shebang {
+        Some(_) if is_executable(&script) => quoted,
+        Some(interpreter) => format!("{} {}", interpreter, quoted),
+        None => format!("sh {}", quoted),
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(file: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(file).is_ok_and(|metadata| metadata.permissions().mode() & 0o111 != 0)
+}
+
+// Windows can't run a script by its shebang
+#[cfg(not(unix))]
+fn is_executable(_file: &Path) -> bool {
+    false
+}
+
+// Remove control characters other than newline and tab, e.g. left by a bad paste, which
+// would garble the terminal when printed. Also returns whether anything was removed.
+fn strip_control_chars(text: &str) -> (String, bool) {
+    let cleaned: String = text.chars().filter(|c| !c.is_control() || *c == '\n' || *c == '\t').collect();
+    let changed = cleaned.len() != text.len();
+    (cleaned, changed)
+}
+
+// Strip control characters from every text of an imported entry, since `list`, `show`
+// and `tags` print them all. Returns whether anything was removed.
+fn sanitize_entry(entry: &mut CommandEntry) -> bool {
+    let mut changed = false;
+    let mut clean = |text: &mut String| {
+        let (cleaned, removed) = strip_control_chars(text);
+        *text = cleaned;
+        changed |= removed;
+    };
+    clean(&mut entry.description);
+    entry.notes.iter_mut()
+    .chain(entry.cwd.iter_mut())
+    .chain(entry.last_output.iter_mut())
+    .chain(entry.tags.iter_mut())
+    .chain(entry.aliases.iter_mut())
+    .for_each(&mut clean);
+    entry.env = std::mem::take(&mut entry.env).into_iter().map(|(mut key, mut value)| {
+        clean(&mut key);
+        clean(&mut value);
+        (key, value)
+    }).collect();
+    changed
+}
+
+fn print_sanitized(affected: &[String]) {
+    if affected.is_empty() {
+        return;
+    }
+    eprintln!("{} removed control characters from {} command(s):", "Warning:".yellow(), affected.len());
+    for cmd in affected {
+        eprintln!("  {}", cmd);
+    }
+}
+
+// Show the numbered list of matches and return the command the user picks
+fn select_command(matching_commands: &[String], store: &CommandStore, action: &str) -> Result<Option<String>> {
+    use std::io::{self, BufRead};
+    println!("Found {} matching commands:", matching_commands.len());
+    for (i, cmd) in matching_commands.iter().enumerate() {
+        println!("[{}] {}{}",
+        i + 1,
+        mask_secrets(cmd).bright_green(),
+        (": ".to_owned() + &store.commands[cmd].description.replace('\n', "\n    ")).blue());
+    };
+    print!("Enter a number to {}: ", action);
+    io::stdout().flush()?;
+
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim().parse::<usize>().ok()
+    .filter(|choice| (1..=matching_commands.len()).contains(choice))
+    .map(|choice| matching_commands[choice - 1].clone()))
+}
+
+// Let the user type to filter all saved commands and pick one
+fn pick_command(store: &CommandStore) -> Result<Option<String>> {
+    if store.commands.is_empty() {
+        println!("No commands saved.");
+        return Ok(None);
+    }
+
+    let mut commands: Vec<&String> = store.commands.keys().collect();
+    sort_commands(&mut commands);
+    let items: Vec<String> = commands.iter()
+    .map(|cmd| format!("{}: {}", mask_secrets(cmd), store.commands[*cmd].description.replace('\n', " ")))
+    .collect();
+    let choice = FuzzySelect::new()
+    .with_prompt("Select a command")
+    .items(&items)
+    .default(0)
+    .interact_opt()
+    .context("Failed to show command picker")?;
+    Ok(choice.map(|i| commands[i].clone()))
+}
+
+fn show_command(path: &Path, pattern: String, output: &OutputOptions) -> Result<()> {
+    let store = CommandStore::load(path)?;
+
+    let matching_commands = search_logic(pattern.clone(), &store, &SearchOptions::default())?;
+    let selected = match matching_commands.len() {
+        0 => {
+            info!("No commands found matching '{}'", pattern);
+            return Ok(());
+        }
+        1 => Some(matching_commands[0].clone()),
+        _ => select_command(&matching_commands, &store, "show")?,
+    };
+    let Some(cmd) = selected else {
+        return Ok(());
+    };
+
+    let entry = &store.commands[&cmd];
+    let tags = if entry.tags.is_empty() { "none".to_string() } else { entry.tags.join(", ") };
+    let shown = if output.reveal { cmd.clone() } else { mask_secrets(&cmd) };
+    println!("{} {}", "Command:    ".bold(), shown.bright_green());
+    println!("{} {}", "Description:".bold(), entry.description.replace('\n', "\n             ").blue());
+    println!("{} {}", "Tags:       ".bold(), tags);
+    if !entry.aliases.is_empty() {
+        println!("{} {}", "Aliases:    ".bold(), entry.aliases.join(", "));
+    }
+    if let Some(notes) = &entry.notes {
+        println!("{} {}", "Notes:      ".bold(), notes.trim_end().replace('\n', "\n             "));
+    }
+    if let Some(cwd) = &entry.cwd {
+        println!("{} {}", "Directory:  ".bold(), cwd);
+    }
+    if !entry.env.is_empty() {
+        let mut env: Vec<String> = entry.env.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+        sort_commands(&mut env);
+        println!("{} {}", "Environment:".bold(), env.join(" "));
+    }
+    println!("{} {}", "Created:    ".bold(), format_timestamp(entry.created_at, "unknown"));
+    println!("{} {}", "Last used:  ".bold(), format_timestamp(entry.last_used, "never"));
+    println!("{} {}", "Times run:  ".bold(), entry.use_count);
+    if entry.last_used.is_some() {
+        println!("{} {}", "Last result:".bold(), format_last_run(entry));
+    }
+    if let Some(output) = entry.last_output.as_ref().filter(|output| !output.is_empty()) {
+        println!("{}", "Last output:".bold());
+        for line in output.lines() {
+            println!("    {}", line.dimmed());
+        }
+    }
+    if entry.pinned {
+        println!("{} yes", "Pinned:     ".bold());
+    }
+    if is_dangerous(&cmd, entry) {
+        println!("{} {}", "Dangerous:  ".bold(), "yes".red());
+    }
+    Ok(())
+}
+
+fn delete_command(path: &Path, args: RemoveArgs) -> Result<()> {
+    let mut store = CommandStore::load(path)?;
+
+    if let Some(exact) = args.exact {
+        if store.commands.shift_remove(&exact).is_some() {
+            warn_pruned(&store.prune_sequences());
+            store.save_with_backup(path)?;
+            info!("Deleted command: {}", exact);
+        } else {
+            info!("No exact match for '{}'", exact);
+            return Err(NoMatch.into());
+        }
+        return Ok(());
+    }
+
+    let pattern = args.pattern.unwrap_or_default();
+    if args.all && pattern.trim().is_empty() {
+        return Err(anyhow::anyhow!("Refusing to delete all commands with an empty pattern"));
+    }
+    let matching_commands = search_logic(pattern.clone(), &store, &args.search)?;
+    if matching_commands.is_empty() {
+        info!("No commands found matching '{}'", pattern);
+        return Err(NoMatch.into());
+    } else if args.all {
+        println!("Found {} matching commands:", matching_commands.len());
+        for cmd in &matching_commands {
+            print_command(cmd, &store.commands[cmd], None, &OutputOptions::default());
+        }
+        if !confirm(&format!("Delete all {} commands?", matching_commands.len()))? {
+            println!("Aborted.");
+            return Ok(());
+        }
+        for cmd in &matching_commands {
+            store.commands.shift_remove(cmd);
+        }
+        warn_pruned(&store.prune_sequences());
+        store.save_with_backup(path)?;
+        info!("Deleted {} commands.", matching_commands.len());
+    } else if args.interactive {
+        let items: Vec<String> = matching_commands.iter()
+        .map(|cmd| format!("{}: {}", cmd, store.commands[cmd].description.replace('\n', " ")))
+        .collect();
+        let chosen = MultiSelect::new()
+        .with_prompt("Select commands to delete (space to toggle, enter to accept)")
+        .items(&items)
+        .interact_opt()
+        .context("Failed to show command list")?
+        .unwrap_or_default();
+        if chosen.is_empty() {
+            info!("Nothing selected.");
+            return Ok(());
+        }
+        if !confirm(&format!("Delete {} commands?", chosen.len()))? {
+            println!("Aborted.");
+            return Ok(());
+        }
+        for i in &chosen {
+            store.commands.shift_remove(&matching_commands[*i]);
+        }
+        warn_pruned(&store.prune_sequences());
+        store.save_with_backup(path)?;
+        info!("Deleted {} commands.", chosen.len());
+    } else if let Some(cmd_to_delete) = select_command(&matching_commands, &store, "delete")? {
+        store.commands.shift_remove(&cmd_to_delete);
+        warn_pruned(&store.prune_sequences());
+        store.save_with_backup(path)?;
+        info!("Deleted command: {}", cmd_to_delete);
+    };
+    Ok(())
+}
+
+fn warn_pruned(sequences: &[String]) {
+    if !sequences.is_empty() {
+        let names: Vec<String> = sequences.iter().map(|name| format!("'{}'", name)).collect();
+        eprintln!("{} deleted commands were also removed from the sequences {}.", "Warning:".yellow(), names.join(", "));
+    }
+}
+
+fn rename_command(path: &Path, pattern: String) -> Result<()> {
+    let mut store = CommandStore::load(path)?;
+
+    let matching_commands = search_logic(pattern.clone(), &store, &SearchOptions::default())?;
+    if matching_commands.is_empty() {
+        info!("No commands found matching '{}'", pattern);
+        return Ok(());
+    }
+    let Some(old_command) = select_command(&matching_commands, &store, "rename")? else {
+        return Ok(());
+    };
+
+    let renamed = prompt_edit("Enter new command: ", &old_command)?;
+    if renamed.is_empty() {
+        return Err(anyhow::anyhow!("Command cannot be empty"));
+    }
+    if renamed == old_command {
+        info!("Command unchanged.");
+        return Ok(());
+    }
+    if let Some(existing) = store.commands.get(&renamed)
+        && !confirm(&format!("Command already exists with description '{}'. Overwrite?", existing.description))? {
+        println!("Aborted.");
+        return Ok(());
+    }
+
+    // The description, tags and history move with the command, which keeps their place in the order of `list --order`
+    let (index, _, entry) = store.commands.shift_remove_full(&old_command).context("Command disappeared from the store")?;
+    store.commands.shift_remove(&renamed);
+    store.commands.shift_insert(index.min(store.commands.len()), renamed.clone(), entry);
+    store.rename_in_sequences(&old_command, &renamed);
+    store.save_with_backup(path)?;
+    info!("Renamed: {} -> {}", old_command, renamed);
+    Ok(())
+}
+
+fn duplicate_command(path: &Path, pattern: String) -> Result<()> {
+    let mut store = CommandStore::load(path)?;
+
+    let matching_commands = search_logic(pattern.clone(), &store, &SearchOptions::default())?;
+    if matching_commands.is_empty() {
+        info!("No commands found matching '{}'", pattern);
+        return Ok(());
+    }
+    let Some(original) = select_command(&matching_commands, &store, "duplicate")? else {
+        return Ok(());
+    };
+
+    let command = prompt_edit("Enter new command: ", &original)?;
+    if command.is_empty() {
+        return Err(anyhow::anyhow!("Command cannot be empty"));
+    }
+    if store.commands.contains_key(&command) {
+        return Err(anyhow::anyhow!("'{}' is already saved. Change the command to make a copy", command));
+    }
+    let template = &store.commands[&original];
+    let description = prompt_edit("Enter description: ", &template.description)?;
+
+    // The copy keeps what describes the command but starts its own history
+    let entry = CommandEntry {
+        description,
+        notes: template.notes.clone(),
+        tags: template.tags.clone(),
+        cwd: template.cwd.clone(),
+        env: template.env.clone(),
+        dangerous: template.dangerous,
+        created_at: Some(Utc::now()),
+        ..Default::default()
+    };
+    store.commands.insert(command.clone(), entry);
+    store.save(path)?;
+    info!("Saved: {}", command);
+    Ok(())
+}
+
+fn describe_command(path: &Path, pattern: String) -> Result<()> {
+    let mut store = CommandStore::load(path)?;
+
+    let matching_commands = search_logic(pattern.clone(), &store, &SearchOptions::default())?;
+    if matching_commands.is_empty() {
+        info!("No commands found matching '{}'", pattern);
+        return Ok(());
+    }
+    let Some(cmd) = select_command(&matching_commands, &store, "describe")? else {
+        return Ok(());
+    };
+
+    // An empty answer keeps the current description
+    let entry = store.commands.get_mut(&cmd).context("Command disappeared from the store")?;
+    let description = prompt_edit(&format!("Enter description [{}]: ", entry.description), "")?;
+    if description.is_empty() {
+        info!("Description unchanged.");
+        return Ok(());
+    }
+    entry.description = description;
+    store.save(path)?;
+    info!("Updated: {}", cmd);
+    Ok(())
+}
+
+// Commands that only differ in case or whitespace are considered the same
+fn normalize_command(cmd: &str) -> String {
+    cmd.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+// Fold another entry into this one, keeping everything either of them knew
+fn merge_entries(entry: &mut CommandEntry, other: CommandEntry) {
+    if entry.description.trim().is_empty() {
+        entry.description = other.description;
+    }
+    entry.notes = entry.notes.take().or(other.notes);
+    for tag in other.tags {
+        if !entry.tags.contains(&tag) {
+            entry.tags.push(tag);
+        }
+    }
+    for alias in other.aliases {
+        if !entry.aliases.contains(&alias) {
+            entry.aliases.push(alias);
+        }
+    }
+    for (key, value) in other.env {
+        entry.env.entry(key).or_insert(value);
+    }
+    entry.cwd = entry.cwd.take().or(other.cwd);
+    entry.created_at = entry.created_at.into_iter().chain(other.created_at).min();
+    if other.last_used > entry.last_used {
+        entry.last_used = other.last_used;
+        entry.last_exit = other.last_exit;
+        entry.last_duration_ms = other.last_duration_ms;
+    }
+    entry.use_count = entry.use_count.saturating_add(other.use_count);
+    entry.pinned |= other.pinned;
+    entry.dangerous |= other.dangerous;
+}
+
+fn dedup_commands(path: &Path, dry_run: bool) -> Result<()> {
+    let mut store = CommandStore::load(path)?;
+
+    let mut groups: HashMap<String, Vec<String>> = HashMap::new();
+    for cmd in store.commands.keys() {
+        groups.entry(normalize_command(cmd)).or_default().push(cmd.clone());
+    }
+    let mut groups: Vec<Vec<String>> = groups.into_values().filter(|group| group.len() > 1).collect();
+    if groups.is_empty() {
+        println!("No duplicate commands found.");
+        return Ok(());
+    }
+    for group in &mut groups {
+        sort_commands(group);
+    }
+    groups.sort_by(|a, b| a[0].to_lowercase().cmp(&b[0].to_lowercase()));
+
+    if dry_run {
+        println!("Found {} groups of duplicates:", groups.len());
+        for group in &groups {
+            println!();
+            for cmd in group {
+                print_command(cmd, &store.commands[cmd], None, &OutputOptions::default());
+            }
+        }
+        return Ok(());
+    }
+
+    // The chosen command keeps its text; the others are merged into it and removed
+    let mut merged = 0;
+    for group in &groups {
+        println!();
+        let Some(keep) = select_command(group, &store, "keep (anything else skips)")? else {
+            continue;
+        };
+        for cmd in group.iter().filter(|cmd| **cmd != keep) {
+            let other = store.commands.shift_remove(cmd).unwrap_or_default();
+            merge_entries(store.commands.get_mut(&keep).context("Command disappeared from the store")?, other);
+            store.rename_in_sequences(cmd, &keep);
+            merged += 1;
+        }
+    }
+    if merged > 0 {
+        store.save_with_backup(path)?;
+    }
+    info!("Merged {} duplicate commands.", merged);
+    Ok(())
+}
+
+fn annotate_commands(path: &Path) -> Result<()> {
+    let mut store = CommandStore::load(path)?;
+
+    let mut commands: Vec<String> = store.commands.iter()
+    .filter(|(_, entry)| entry.description.trim().is_empty())
+    .map(|(cmd, _)| cmd.clone())
+    .collect();
+    if commands.is_empty() {
+        println!("Every command has a description.");
+        return Ok(());
+    }
+    sort_commands(&mut commands);
+
+    // Enter skips a command; everything is saved at the end
+    println!("{} commands have no description. Press Enter to skip one.", commands.len());
+    let mut annotated = 0;
+    for cmd in &commands {
+        println!("$ {}", cmd.bright_green());
+        let description = prompt("Enter description: ")?;
+        if !description.is_empty() {
+            store.commands.get_mut(cmd).context("Command disappeared from the store")?.description = description;
+            annotated += 1;
+        }
+    }
+    if annotated > 0 {
+        store.save(path)?;
+    }
+    info!("Annotated {} commands.", annotated);
+    Ok(())
+}
+
+fn tag_commands(path: &Path, pattern: String, add: Vec<String>, remove: Vec<String>) -> Result<()> {
+    let mut store = CommandStore::load(path)?;
+
+    let matching_commands = search_logic(pattern.clone(), &store, &SearchOptions::default())?;
+    if matching_commands.is_empty() {
+        info!("No commands found matching '{}'", pattern);
+        return Ok(());
+    }
+    println!("Found {} matching commands:", matching_commands.len());
+    for cmd in &matching_commands {
+        print_command(cmd, &store.commands[cmd], None, &OutputOptions::default());
+    }
+
+    // Without changes to make, listing the matches and their tags is all there is to do
+    let add: Vec<String> = add.iter().map(|tag| tag.trim().to_string()).filter(|tag| !tag.is_empty()).collect();
+    let remove: Vec<String> = remove.iter().map(|tag| tag.trim().to_lowercase()).filter(|tag| !tag.is_empty()).collect();
+    if add.is_empty() && remove.is_empty() {
+        return Ok(());
+    }
+    if !confirm(&format!("Update the tags of {} commands?", matching_commands.len()))? {
+        println!("Aborted.");
+        return Ok(());
+    }
+
+    let mut modified = 0;
+    for cmd in &matching_commands {
+        let entry = store.commands.get_mut(cmd).context("Command disappeared from the store")?;
+        let before = entry.tags.clone();
+        entry.tags.retain(|tag| !remove.contains(&tag.to_lowercase()));
+        for tag in &add {
+            if !entry.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+                entry.tags.push(tag.clone());
+            }
+        }
+        if entry.tags != before {
+            modified += 1;
+        }
+    }
+    if modified > 0 {
+        store.save(path)?;
+    }
+    info!("Updated {} commands.", modified);
+    Ok(())
+}
+
+fn list_tags(path: &Path) -> Result<()> {
+    let store = CommandStore::load(path)?;
+
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for entry in store.commands.values() {
+        for tag in &entry.tags {
+            *counts.entry(tag.clone()).or_default() += 1;
+        }
+    }
+    if counts.is_empty() {
+        println!("No tags defined.");
+        return Ok(());
+    }
+
+    let mut tags: Vec<&String> = counts.keys().collect();
+    sort_commands(&mut tags);
+    tags.sort_by(|a, b| counts[*b].cmp(&counts[*a]));
+    for tag in tags {
+        println!("{} {}", tag.color(tag_color(tag)), format!("({})", counts[tag]).dimmed());
+    }
+    Ok(())
+}
+
+fn pin_command(path: &Path, pattern: String, pinned: bool) -> Result<()> {
+    let mut store = CommandStore::load(path)?;
+
+    let action = if pinned { "pin" } else { "unpin" };
+    let matching_commands = search_logic(pattern.clone(), &store, &SearchOptions::default())?;
+    if matching_commands.is_empty() {
+        info!("No commands found matching '{}'", pattern);
+        return Ok(());
+    }
+    let Some(cmd) = select_command(&matching_commands, &store, action)? else {
+        return Ok(());
+    };
+
+    let entry = store.commands.get_mut(&cmd).context("Command disappeared from the store")?;
+    if entry.pinned == pinned {
+        info!("Already {}ned: {}", action, cmd);
+        return Ok(());
+    }
+    entry.pinned = pinned;
+    store.save(path)?;
+    info!("{}: {}", if pinned { "Pinned" } else { "Unpinned" }, cmd);
+    Ok(())
+}
+
+fn move_command(path: &Path, pattern: String, direction: MoveDirection) -> Result<()> {
+    let mut store = CommandStore::load(path)?;
+
+    let matching_commands = search_logic(pattern.clone(), &store, &SearchOptions::default())?;
+    if matching_commands.is_empty() {
+        info!("No commands found matching '{}'", pattern);
+        return Ok(());
+    }
+    let Some(cmd) = select_command(&matching_commands, &store, "move")? else {
+        return Ok(());
+    };
+
+    let from = store.commands.get_index_of(&cmd).context("Command disappeared from the store")?;
+    let last = store.commands.len() - 1;
+    let to = if direction.up {
+        from.saturating_sub(1)
+    } else if direction.down {
+        (from + 1).min(last)
+    } else if direction.top {
+        0
+    } else {
+        last
+    };
+    if to == from {
+        info!("Already at the {}: {}", if from == 0 { "top" } else { "bottom" }, cmd);
+        return Ok(());
+    }
+    store.commands.move_index(from, to);
+    store.save(path)?;
+    info!("Moved to position {} of {}: {}", to + 1, last + 1, cmd);
+    Ok(())
+}
+
+// Quote an argument for the shell unless it only has characters that are safe as they are
+fn shell_quote(arg: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "-_=+.,/:@".contains(c);
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        return arg.to_string();
+    }
+    if cfg!(target_os = "windows") {
+        format!("\"{}\"", arg.replace('"', "\"\""))
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+// Commands matching any of these are treated as dangerous even when not marked
+const DANGEROUS_PATTERNS: &[&str] = &[
+    r"\bdd\s+.*\bof=",
+    r"\bmkfs(\.\w+)?\b",
+    r":\(\)\s*\{",
+    r">\s*/dev/(sd|hd|nvme|disk)",
+];
+
+// A command is dangerous when it was marked with --dangerous or looks destructive.
+// Placeholders are filled in first, so the check sees what actually runs.
+fn is_dangerous(cmd: &str, entry: &CommandEntry) -> bool {
+    entry.dangerous || is_forced_recursive_rm(cmd) || DANGEROUS_PATTERNS.iter().any(|pattern| {
+        RegexBuilder::new(pattern).case_insensitive(true).build().is_ok_and(|regex| regex.is_match(cmd))
+    })
+}
+
+// Whether an `rm` of the command line gets both a recursive and a force flag, in any
+// order and spelling, e.g. `rm -rf`, `rm -r -f` or `rm --recursive -f`
+fn is_forced_recursive_rm(cmd: &str) -> bool {
+    cmd.split([';', '&', '|', '\n']).any(|part| {
+        let mut words = part.split_whitespace().skip_while(|word| *word != "rm" && !word.ends_with("/rm"));
+        if words.next().is_none() {
+            return false;
+        }
+        let (mut recursive, mut force) = (false, false);
+        for word in words.take_while(|word| *word != "--").filter(|word| word.starts_with('-')) {
+            match word.strip_prefix("--") {
+                Some(long) => {
+                    recursive |= long == "recursive";
+                    force |= long == "force";
+                }
+                None => {
+                    recursive |= word.contains(['r', 'R']);
+                    force |= word.contains('f');
+                }
+            }
+        }
+        recursive && force
+    })
+}
+
+// Replace a leading `~` with the home directory
+fn expand_tilde(path: &str) -> PathBuf {
+    if let Some(home) = dirs::home_dir() {
+        if path == "~" {
+            return home;
+        }
+        if let Some(rest) = path.strip_prefix("~/").or_else(|| path.strip_prefix("~\\")) {
+            return home.join(rest);
+        }
+    }
+    PathBuf::from(path)
+}
+
+// Look up a program in $PATH, returning its full path if it exists
+fn find_in_path(program: &str) -> Option<PathBuf> {
+    if program.contains(['/', '\\']) {
+        let path = PathBuf::from(program);
+        return if path.is_file() { Some(path) } else { None };
+    }
+    let extensions: Vec<String> = if cfg!(target_os = "windows") {
+        std::env::var("PATHEXT").unwrap_or_else(|_| ".EXE;.BAT;.CMD".to_string())
+        .split(';').map(|ext| ext.to_string()).collect()
+    } else {
+        vec![String::new()]
+    };
+    std::env::split_paths(&std::env::var_os("PATH")?).find_map(|dir| {
+        extensions.iter()
+        .map(|ext| dir.join(format!("{}{}", program, ext)))
+        .find(|candidate| candidate.is_file())
+    })
+}
+
+// Resolve the shell as --shell, then $SHELL, then sh or cmd, along with the argument that
+// makes it run a command string
+fn find_shell(shell: Option<String>) -> Result<(String, &'static str)> {
+    let shell = shell.filter(|value| !value.trim().is_empty())
+    .or_else(|| std::env::var("SHELL").ok().filter(|value| !value.trim().is_empty()));
+    let Some(shell) = shell else {
+        return Ok(if cfg!(target_os = "windows") {
+            ("cmd".to_string(), "/C")
+        } else {
+            ("sh".to_string(), "-c")
+        });
+    };
+    if find_in_path(&shell).is_none() {
+        return Err(anyhow::anyhow!("Shell not found: {}", shell));
+    }
+    let name = Path::new(&shell).file_stem().and_then(|stem| stem.to_str()).unwrap_or_default().to_lowercase();
+    let shell_arg = match name.as_str() {
+        "cmd" => "/C",
+        "pwsh" | "powershell" => "-Command",
+        _ => "-c",
+    };
+    Ok((shell, shell_arg))
+}
+
+// Resolve the editor as --editor, then $VISUAL, then $EDITOR, then the config file, then
+// the first common editor that is installed. The result is split into the program and
+// its arguments.
+fn find_editor(editor: Option<String>, config: &Config) -> Result<Vec<String>> {
+    let from_env = |name: &str| std::env::var(name).ok().filter(|value| !value.trim().is_empty());
+    let editor = editor.filter(|value| !value.trim().is_empty())
+    .or_else(|| from_env("VISUAL"))
+    .or_else(|| from_env("EDITOR"))
+    .or_else(|| config.editor.clone().filter(|value| !value.trim().is_empty()))
+    .or_else(|| {
+        ["nano", "vi", "notepad"].iter()
+        .find(|candidate| find_in_path(candidate).is_some())
+        .map(|candidate| candidate.to_string())
+    })
+    .context("No editor found. Set $EDITOR or pass --editor <command>")?;
+    Ok(editor.split_whitespace().map(|part| part.to_string()).collect())
+}
+
+// Escape backslashes, newlines and any colon that could run into the `:::` separator,
+// so values survive the editor round trip
+fn escape_edit_value(value: &str) -> String {
+    let mut escaped = String::new();
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            ':' if matches!(chars.peek(), Some(':') | None) => escaped.push_str("\\:"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Split an edited line on the first unescaped `:::` and unescape both halves
+fn parse_edit_line(line: &str) -> Option<(String, String)> {
+    let mut command = String::new();
+    let mut description: Option<String> = None;
+    let mut chars = line.char_indices();
+    while let Some((i, c)) = chars.next() {
+        let in_command = description.is_none();
+        let field = description.as_mut().unwrap_or(&mut command);
+        if c == '\\' {
+            match chars.next() {
+                Some((_, 'n')) => field.push('\n'),
+                Some((_, escaped)) => field.push(escaped),
+                None => field.push('\\'),
+            }
+        } else if in_command && line[i..].starts_with(":::") {
+            chars.nth(1);
+            description = Some(String::new());
+        } else {
+            field.push(c);
+        }
+    }
+    description.map(|description| (command, description))
+}
+
+fn edit_commands(path: &Path, editor: Option<String>, config: &Config) -> Result<()> {
+    // Remember the file as it was, to notice changes made while the editor is open
+    let original = fs::read(path).ok();
+    let mut store = CommandStore::load(path)?;
+    // The editor would see, and maybe back up, every command in plain text
+    if store.encrypted {
+        return Err(anyhow::anyhow!("{} is encrypted, so it can't be opened in an editor. Use `keepc describe` or `keepc rename` instead", path.display()));
+    }
+
+    // Create and write commands a temporary file
+    let mut temp_file = NamedTempFile::new().context("Failed to create temporary file")?;
+    for (cmd, entry) in &store.commands {
+        writeln!(temp_file, "{}:::{}", escape_edit_value(cmd), escape_edit_value(&entry.description))
+        .context("Failed to write to temp file")?;
+    }
+    let temp_path = temp_file.path().to_owned();
+    temp_file.flush().context("Failed to flush temp file")?;
+    let editor = find_editor(editor, config)?;
+    let status = Command::new(&editor[0])
+    .args(&editor[1..])
+    .arg(&temp_path)
+    .status()
+    .context(format!("Failed to open editor: {}", editor.join(" ")))?;
+
+    if !status.success() {
+        return Err(anyhow::anyhow!("Editor exited with non-zero status"));
+    }
+    let mut content = String::new();
+    std::io::Read::read_to_string(
+        &mut File::open(&temp_path).context("Failed to open temporary file after editing")?,
+        &mut content
+    ).context("Failed to read temporary file after editing")?;
+    let mut new_commands = IndexMap::new();
+    let mut skipped_lines = Vec::new();
+    let mut sanitized_lines = Vec::new();
+    // While no line was added or removed, a changed command on a line is the one
+    // written there, renamed
+    let mut renames = Vec::new();
+    let same_lines = content.lines().count() == store.commands.len();
+    for (number, line) in content.lines().enumerate() {
+        match parse_edit_line(line) {
+            Some((cmd, desc)) if !cmd.trim().is_empty() => {
+                let (cmd, cmd_changed) = strip_control_chars(cmd.trim());
+                let (desc, desc_changed) = strip_control_chars(desc.trim());
+                if cmd_changed || desc_changed {
+                    sanitized_lines.push(format!("line {}: {}", number + 1, cmd));
+                }
+                if cmd.trim().is_empty() {
+                    continue;
+                }
+                // Keep tags and other metadata of commands that survive the edit
+                let cmd = cmd.trim().to_string();
+                let mut entry = store.commands.get(&cmd).cloned()
+                .unwrap_or_else(|| CommandEntry { created_at: Some(Utc::now()), ..Default::default() });
+                entry.description = desc.trim().to_string();
+                if same_lines
+                    && let Some((old, _)) = store.commands.get_index(number)
+                    && *old != cmd {
+                    renames.push((old.clone(), cmd.clone()));
+                }
+                new_commands.insert(cmd, entry);
+            }
+            _ if line.trim().is_empty() => {}
+            _ => skipped_lines.push(line),
+        }
+    }
+    print_sanitized(&sanitized_lines);
+
+    // A line without the separator would silently delete its command
+    if !skipped_lines.is_empty() {
+        println!("{} {} line(s) could not be parsed and were ignored:", "Warning:".yellow(), skipped_lines.len());
+        for line in &skipped_lines {
+            println!("  {}", line);
+        }
+        if !confirm("Save anyway?")? {
+            println!("Aborted.");
+            return Ok(());
+        }
+    }
+    if fs::read(path).ok() != original {
+        println!("{} {} was changed by another process while you were editing.", "Warning:".yellow(), path.display());
+        if !confirm("Overwrite those changes?")? {
+            println!("Aborted.");
+            return Ok(());
+        }
+    }
+    // Surviving commands keep their place, new ones go after them
+    new_commands.sort_by(|a, _, b, _| {
+        let position = |cmd| store.commands.get_index_of(cmd).unwrap_or(usize::MAX);
+        position(a).cmp(&position(b))
+    });
+    for (old, new) in renames.iter().filter(|(old, _)| !new_commands.contains_key(old)) {
+        store.rename_in_sequences(old, new);
+    }
+    store.commands = new_commands;
+    warn_pruned(&store.prune_sequences());
+    store.save_with_backup(path)?;
+
+    info!("Commands updated.");
+    Ok(())
+}
+
+// Collect the unique `{{name}}` placeholders of a command in order of appearance
+fn find_placeholders(command: &str) -> Vec<String> {
+    let mut placeholders = Vec::new();
+    let mut rest = command;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else { break };
+        let name = after[..end].trim().to_string();
+        if !name.is_empty() && !placeholders.contains(&name) {
+            placeholders.push(name);
+        }
+        rest = &after[end + 2..];
+    }
+    placeholders
+}
+
+// Replace every `{{name}}` placeholder that has a value, leaving the others untouched
+fn substitute_placeholders(command: &str, values: &HashMap<String, String>) -> String {
+    let mut result = String::new();
+    let mut rest = command;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else { break };
+        result.push_str(&rest[..start]);
+        match values.get(after[..end].trim()) {
+            Some(value) => result.push_str(value),
+            None => result.push_str(&rest[start..start + end + 4]),
+        }
+        rest = &after[end + 2..];
+    }
+    result.push_str(rest);
+    result
+}
+
+// Placeholders named `secret:<name>` hold values that shouldn't end up in scrollback
+const SECRET_PREFIX: &str = "secret:";
+
+// Replace every `{{secret:name}}` placeholder with ****
+fn mask_secrets(command: &str) -> String {
+    let values: HashMap<String, String> = find_placeholders(command).into_iter()
+    .filter(|name| name.starts_with(SECRET_PREFIX))
+    .map(|name| (name, "****".to_string()))
+    .collect();
+    substitute_placeholders(command, &values)
+}
+
+// Prompt for a value for each placeholder, asking again when left blank. Values of
+// secret placeholders are read without echo. Returns the command to run, the command to
+// show, in which secrets are masked, and the secret values.
+fn fill_placeholders(command: &str) -> Result<(String, String, Vec<String>)> {
+    use std::io::{self, BufRead};
+    let mut values = HashMap::new();
+    for name in find_placeholders(command) {
+        if let Some(label) = name.strip_prefix(SECRET_PREFIX) {
+            let value = dialoguer::Password::new()
+            .with_prompt(format!("Enter value for {}", label.trim().bright_yellow()))
+            .interact()
+            .context("Failed to read secret value")?;
+            values.insert(name, value);
+            continue;
+        }
+        let value = loop {
+            print!("Enter value for {}: ", name.bright_yellow());
+            io::stdout().flush()?;
+            let mut line = String::new();
+            if io::stdin().lock().read_line(&mut line)? == 0 {
+                return Err(anyhow::anyhow!("No value given for placeholder '{}'", name));
+            }
+            let value = line.trim().to_string();
+            if !value.is_empty() {
+                break value;
+            }
+        };
+        values.insert(name, value);
+    }
+    let command_to_run = substitute_placeholders(command, &values);
+    let mut secrets = Vec::new();
+    for (name, value) in values.iter_mut() {
+        if name.starts_with(SECRET_PREFIX) {
+            secrets.push(std::mem::replace(value, "****".to_string()));
+        }
+    }
+    Ok((command_to_run, substitute_placeholders(command, &values), secrets))
+}
+
+// Print a prompt and read one trimmed line of input
+fn prompt(message: &str) -> Result<String> {
+    use std::io::{self, BufRead};
+    print!("{}", message);
+    io::stdout().flush()?;
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim().to_string())
+}
+
+// Like `prompt`, but with line editing and the history of earlier answers when stdin is a
+// terminal. The answer starts out as `initial`.
+fn prompt_edit(message: &str, initial: &str) -> Result<String> {
+    use rustyline::error::ReadlineError;
+    if !std::io::stdin().is_terminal() {
+        return prompt(message);
+    }
+    let Ok(mut editor) = rustyline::DefaultEditor::new() else {
+        return prompt(message);
+    };
+    let history = get_config_dir()?.join("history.txt");
+    let _ = editor.load_history(&history);
+
+    let line = match editor.readline_with_initial(message, (initial, "")) {
+        Ok(line) => line.trim().to_string(),
+        Err(ReadlineError::Eof) => String::new(),
+        Err(ReadlineError::Interrupted) => return Err(anyhow::anyhow!("Interrupted")),
+        Err(err) => return Err(err).context("Failed to read input"),
+    };
+    if !line.is_empty() && !NO_HISTORY.load(Ordering::Relaxed) {
+        // Losing the history is no reason to lose the answer
+        let _ = editor.add_history_entry(line.as_str());
+        let _ = fs::create_dir_all(get_config_dir()?);
+        let _ = editor.save_history(&history);
+    }
+    Ok(line)
+}
+
+// Print a prompt and read lines until an empty line or end of input, joined by newlines
+fn prompt_multiline(message: &str) -> Result<String> {
+    use std::io::{self, BufRead};
+    println!("{}", message);
+    let mut lines = Vec::new();
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            break;
+        }
+        lines.push(line.trim_end().to_string());
+    }
+    Ok(lines.join("\n").trim().to_string())
+}
+
+// Like `prompt`, but reads the answer from the terminal even when stdin is piped
+fn prompt_tty(message: &str) -> Result<String> {
+    use std::io::{BufRead, BufReader};
+    let tty = if cfg!(target_os = "windows") { "CONIN$" } else { "/dev/tty" };
+    let Ok(tty) = File::open(tty) else {
+        return Ok(String::new());
+    };
+    print!("{}", message);
+    std::io::stdout().flush()?;
+    let mut line = String::new();
+    BufReader::new(tty).read_line(&mut line)?;
+    Ok(line.trim().to_string())
+}
+
+// Ask a yes/no question, defaulting to no
+fn confirm(prompt: &str) -> Result<bool> {
+    use std::io::{self, BufRead};
+    print!("{} [y/N] ", prompt);
+    io::stdout().flush()?;
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(matches!(line.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+fn execute_command(path: &Path, args: RunArgs, config: &Config) -> Result<()> {
+    let store = CommandStore::load(path)?;
+    // A wrong --dir fails before the menu or picker asks for anything
+    run_dir(args.dir.as_deref())?;
+
+    // Pick by list number, or interactively from every saved command without a pattern
+    let selected = match (&args.pattern, args.from_list) {
+        (_, Some(number)) => {
+            let commands = listed_commands(&store, config.sort);
+            Some(number.checked_sub(1).and_then(|i| commands.get(i)).map(|cmd| cmd.to_string())
+            .with_context(|| format!("No command number {}: {} commands are saved", number, commands.len()))?)
+        }
+        (None, None) => pick_command(&store)?,
+        (Some(pattern), None) => {
+            let matching_commands = search_logic(pattern.clone(), &store, &args.search)?;
+            if matching_commands.is_empty() {
+                info!("No commands found matching '{}'", pattern);
+                return Err(NoMatch.into());
+            }
+            // Matches are numbered from 1, as in the menu
+            match (args.index, args.first) {
+                (Some(index), _) => Some(index.checked_sub(1)
+                .and_then(|i| matching_commands.get(i)).cloned()
+                .with_context(|| format!("No match number {}: {} commands match '{}'", index, matching_commands.len(), pattern))?),
+                // A single match needs no menu, the confirmation still shows it
+                (None, _) if matching_commands.len() == 1 => Some(matching_commands[0].clone()),
+                (None, true) => Some(matching_commands[0].clone()),
+                (None, false) => select_command(&matching_commands, &store, "execute")?,
+            }
+        }
+    };
+    let Some(selected) = selected else {
+        return Ok(());
+    };
+    run_selected(path, store, selected, args)
+}
+
+// The directory given with `run --dir`, with `~` and variables expanded
+fn run_dir(dir: Option<&str>) -> Result<Option<PathBuf>> {
+    let Some(dir) = dir else {
+        return Ok(None);
+    };
+    let expanded = expand_tilde(&expand_env_vars(dir.trim()));
+    if !expanded.is_dir() {
+        return Err(anyhow::anyhow!("Directory {} does not exist", expanded.display()));
+    }
+    Ok(Some(expanded))
+}
+
+// Fill in, confirm and run a command picked by `run` or `browse`
+fn run_selected(path: &Path, mut store: CommandStore, selected: String, args: RunArgs) -> Result<()> {
+    let dir = run_dir(args.dir.as_deref())?;
+
+    // Secrets are masked in everything printed, only the shell sees them
+    let (mut cmd_to_execute, mut cmd_to_show, secrets) = fill_placeholders(&selected)?;
+    for arg in &args.extra_args {
+        let arg = format!(" {}", shell_quote(arg));
+        cmd_to_execute.push_str(&arg);
+        cmd_to_show.push_str(&arg);
+    }
+    let mut cwd = dir.or_else(|| store.commands[&selected].cwd.as_deref().map(expand_tilde));
+    if args.dry_run {
+        match &cwd {
+            Some(cwd) => println!("Would execute in {}: {}", cwd.display(), cmd_to_show),
+            None => println!("Would execute: {}", cmd_to_show),
+        }
+        return Ok(());
+    }
+    if let Some(dir) = &cwd
+        && !dir.is_dir() {
+        eprintln!("{} directory {} does not exist.", "Warning:".yellow(), dir.display());
+        if !confirm("Run from the current directory instead?")? {
+            println!("Aborted.");
+            return Ok(());
+        }
+        cwd = None;
+    }
+    // Preview exactly what is started: the shell with its command string, or the argv of
+    // the program. Secret values stay masked.
+    let mask = |text: &str| secrets.iter().filter(|secret| !secret.is_empty())
+    .fold(text.to_string(), |text, secret| text.replace(secret.as_str(), "****"));
+    let (command, preview) = match args.no_shell.then(|| split_simple_command(&cmd_to_execute, &store.commands[&selected].env)).flatten() {
+        Some(words) => {
+            let mut command = Command::new(&words[0]);
+            // Expanded like the shell's exports would be
+            let env = store.commands[&selected].env.iter().map(|(key, value)| (key, expand_env_vars(value)));
+            command.args(&words[1..]).envs(env);
+            let argv: Vec<String> = words.iter().map(|word| format!("{:?}", mask(word))).collect();
+            (command, format!("[{}]", argv.join(", ")))
+        }
+        None => {
+            if args.no_shell {
+                info!("{} the command uses shell syntax, running it with the shell.", "Note:".yellow());
+            }
+            let (shell, shell_arg) = find_shell(args.shell)?;
+            let env = &store.commands[&selected].env;
+            let preview = format!("{} {} {}", shell, shell_arg, shell_quote(&shell_line(shell_arg, &cmd_to_show, env)));
+            (shell_command(&shell, shell_arg, &cmd_to_execute, env), preview)
+        }
+    };
+    println!("{} {}", "Will run:".bold(), preview.bright_green());
+    if is_dangerous(&cmd_to_execute, &store.commands[&selected]) {
+        println!("{}", "Warning: this command is marked as dangerous and may destroy data.".red().bold());
+        if prompt("Type 'yes' to run it: ")? != "yes" {
+            println!("Aborted.");
+            return Ok(());
+        }
+    } else if !args.yes && !confirm("Run this command?")? {
+        println!("Aborted.");
+        return Ok(());
+    }
+    info!("Executing: {}", cmd_to_show);
+    let entry = store.commands.get_mut(&selected).context("Command disappeared from the store")?;
+    spawn_command(command, &cmd_to_show, entry, cwd.as_deref(), args.capture)?;
+    store.save_usage(path)?;
+    Ok(())
+}
+
+// Most lines of captured output printed per stream, and kept of stdout on the entry
+const MAX_CAPTURED_LINES: usize = 200;
+const MAX_STORED_LINES: usize = 20;
+
+// Characters that need a shell to mean what they say
+const SHELL_SYNTAX: &[char] = &['|', '&', ';', '<', '>', '(', ')', '$', '`', '*', '?', '[', ']', '{', '}', '~', '\n'];
+
+// Split a command line into a program and its arguments, or None when it needs a shell.
+// Without a shell keepc expands `~` at the start of a word and `$VAR` or `${VAR}` itself,
+// taking variables from the entry's --env first; globs, command substitution and
+// everything else are left to the shell.
+fn split_simple_command(command_line: &str, env: &HashMap<String, String>) -> Option<Vec<String>> {
+    if command_line.contains("$(") || command_line.contains('`') {
+        return None;
+    }
+    let expanded = expand_variables(command_line, env);
+    if expanded.contains(SHELL_SYNTAX) {
+        return None;
+    }
+    shlex::split(&expanded).filter(|words| !words.is_empty())
+}
+
+// Expand `~` at the start of an unquoted word to the home directory and `$VAR` or `${VAR}`
+// to the value of the variable, empty when unset. Like in the shell nothing is expanded
+// inside single quotes or after a backslash, and `~` isn't expanded inside double quotes.
+fn expand_variables(command_line: &str, env: &HashMap<String, String>) -> String {
+    let home = dirs::home_dir().map(|home| shell_quote(&home.to_string_lossy()));
+    let chars: Vec<char> = command_line.chars().collect();
+    let mut expanded = String::new();
+    let (mut in_single, mut in_double) = (false, false);
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        i += 1;
+        if in_single {
+            in_single = c != '\'';
+            expanded.push(c);
+            continue;
+        }
+        match c {
+            '\'' if !in_double => {
+                in_single = true;
+                expanded.push(c);
+            }
+            '"' => {
+                in_double = !in_double;
+                expanded.push(c);
+            }
+            '\\' => {
+                expanded.push(c);
+                if let Some(next) = chars.get(i) {
+                    expanded.push(*next);
+                    i += 1;
+                }
+            }
+            '~' if !in_double
+                && (i == 1 || chars[i - 2].is_whitespace())
+                && chars.get(i).is_none_or(|next| *next == '/' || next.is_whitespace()) => {
+                expanded.push_str(home.as_deref().unwrap_or("~"));
+            }
+            '$' => {
+                let braced = chars.get(i) == Some(&'{');
+                let start = if braced { i + 1 } else { i };
+                let end = start + chars[start.min(chars.len())..].iter()
+                .take_while(|c| c.is_alphanumeric() || **c == '_').count();
+                if start == end || (braced && chars.get(end) != Some(&'}')) {
+                    expanded.push(c);
+                    continue;
+                }
+                let name: String = chars[start..end].iter().collect();
+                let value = env.get(&name).cloned().or_else(|| std::env::var(&name).ok()).unwrap_or_default();
+                if in_double {
+                    expanded.push_str(&value.replace('\\', "\\\\").replace('"', "\\\""));
+                } else {
+                    expanded.push_str(&value);
+                }
+                i = if braced { end + 1 } else { end };
+            }
+            _ => expanded.push(c),
+        }
+    }
+    expanded
+}
+
+// Replace `$VAR` and `${VAR}` with the value of the variable, empty when unset
+fn expand_env_vars(text: &str) -> String {
+    let variable = Regex::new(r"\$(?:\{(\w+)\}|(\w+))").expect("valid regex");
+    variable.replace_all(text, |caps: &regex::Captures| {
+        let name = caps.get(1).or_else(|| caps.get(2)).map_or("", |name| name.as_str());
+        std::env::var(name).unwrap_or_default()
+    }).into_owned()
+}
+
+// The shell running a command line, e.g. `sh -c 'ls -la'`, with the variables of its entry
+fn shell_command(shell: &str, shell_arg: &str, command_line: &str, env: &HashMap<String, String>) -> Command {
+    let mut command = Command::new(shell);
+    command.arg(shell_arg).arg(shell_line(shell_arg, command_line, env));
+    if shell_arg != "-c" {
+        command.envs(env);
+    }
+    command
+}
+
+// The command line a shell is given. A POSIX shell exports the variables itself, so
+// `$VAR` in a value is expanded the same way as in the command, e.g. `PATH=$HOME/bin:$PATH`.
+fn shell_line(shell_arg: &str, command_line: &str, env: &HashMap<String, String>) -> String {
+    if shell_arg != "-c" {
+        return command_line.to_string();
+    }
+    let mut keys: Vec<&String> = env.keys().collect();
+    keys.sort();
+    let exports: String = keys.iter()
+    .map(|key| format!("export {}=\"{}\"; ", key, env[*key].replace('\\', "\\\\").replace('"', "\\\"")))
+    .collect();
+    exports + command_line
+}
+
+// Run a command in the directory of its entry, and record the run on the entry. A failing command is recorded like any other run, it isn't an error of keepc.
+// With `capture` the output is collected and printed afterwards.
+fn spawn_command(mut command: Command, shown: &str, entry: &mut CommandEntry, cwd: Option<&Path>, capture: bool) -> Result<ExitStatus> {
+    if let Some(cwd) = cwd {
+        command.current_dir(cwd);
+    }
+    command.stdin(Stdio::inherit());
+    let started = std::time::Instant::now();
+    let status = if capture {
+        let output = command.output().context(format!("Failed to execute: {}", shown))?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        print_captured("stdout", &stdout);
+        print_captured("stderr", &String::from_utf8_lossy(&output.stderr));
+        let lines: Vec<&str> = stdout.lines().collect();
+        entry.last_output = Some(lines[lines.len().saturating_sub(MAX_STORED_LINES)..].join("\n"));
+        output.status
+    } else {
+        command.stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+        .context(format!("Failed to execute: {}", shown))?
+    };
+    let duration = started.elapsed();
+
+    entry.last_used = Some(Utc::now());
+    entry.use_count += 1;
+    entry.last_exit = status.code();
+    entry.last_duration_ms = Some(u64::try_from(duration.as_millis()).unwrap_or(u64::MAX));
+    Ok(status)
+}
+
+// Print one stream of captured output under a label, cut short when it's long
+fn print_captured(label: &str, output: &str) {
+    if output.trim().is_empty() {
+        return;
+    }
+    println!("{}", format!("--- {} ---", label).bold());
+    let lines: Vec<&str> = output.lines().collect();
+    for line in lines.iter().take(MAX_CAPTURED_LINES) {
+        println!("{}", line);
+    }
+    if lines.len() > MAX_CAPTURED_LINES {
+        println!("{}", format!("... {} more lines not shown", lines.len() - MAX_CAPTURED_LINES).dimmed());
+    }
+}
+
+fn new_sequence(path: &Path, name: String, commands: Vec<String>, force: bool) -> Result<()> {
+    let mut store = CommandStore::load(path)?;
+
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err(anyhow::anyhow!("Sequence name cannot be empty"));
+    }
+    if let Some(missing) = commands.iter().find(|cmd| !store.commands.contains_key(*cmd)) {
+        return Err(anyhow::anyhow!("'{}' is not a saved command. Quote each command of the sequence exactly as it was saved", missing));
+    }
+    if store.sequences.contains_key(&name)
+        && !force
+        && !confirm(&format!("Sequence '{}' already exists. Overwrite?", name))? {
+        println!("Aborted.");
+        return Ok(());
+    }
+    store.sequences.insert(name.clone(), commands);
+    store.save(path)?;
+    info!("Saved sequence: {}", name);
+    Ok(())
+}
+
+fn run_sequence(path: &Path, name: String, yes: bool, keep_going: bool, shell: Option<String>) -> Result<()> {
+    let mut store = CommandStore::load(path)?;
+
+    let steps = store.sequences.get(&name).cloned()
+    .with_context(|| format!("No sequence named '{}'", name))?;
+    if let Some(missing) = steps.iter().find(|cmd| !store.commands.contains_key(*cmd)) {
+        return Err(anyhow::anyhow!("Sequence '{}' runs '{}', which is no longer saved", name, missing));
+    }
+    let (shell, shell_arg) = find_shell(shell)?;
+    if !yes {
+        for (i, cmd) in steps.iter().enumerate() {
+            println!("[{}] {}", i + 1, mask_secrets(cmd).bright_green());
+        }
+        if !confirm(&format!("Run these {} commands?", steps.len()))? {
+            println!("Aborted.");
+            return Ok(());
+        }
+    }
+
+    let mut failed = 0;
+    for (i, cmd) in steps.iter().enumerate() {
+        let (command_line, shown, _) = fill_placeholders(cmd)?;
+        println!("{} $ {}", format!("[{}/{}]", i + 1, steps.len()).bold(), shown.bright_green());
+        let entry = store.commands.get_mut(cmd).context("Command disappeared from the store")?;
+        if is_dangerous(&command_line, entry) {
+            println!("{}", "Warning: this command is marked as dangerous and may destroy data.".red().bold());
+            if prompt("Type 'yes' to run it: ")? != "yes" {
+                println!("Aborted.");
+                break;
+            }
+        }
+        let cwd = entry.cwd.as_deref().map(expand_tilde);
+        let status = match cwd.as_deref().filter(|cwd| !cwd.is_dir()) {
+            Some(missing) => {
+                eprintln!("{} directory {} does not exist.", "Warning:".yellow(), missing.display());
+                None
+            }
+            None => Some(spawn_command(shell_command(&shell, shell_arg, &command_line, &entry.env), &shown, entry, cwd.as_deref(), false)?),
+        };
+        if status.is_some_and(|status| status.success()) {
+            continue;
+        }
+        failed += 1;
+        if !keep_going {
+            store.save_usage(path)?;
+            return Err(anyhow::anyhow!("Step {} of '{}' failed, stopping. Use --continue to keep going", i + 1, name));
+        }
+    }
+    store.save_usage(path)?;
+    if failed > 0 {
+        return Err(anyhow::anyhow!("{} of {} steps of '{}' failed", failed, steps.len(), name));
+    }
+    Ok(())
+}
+
+fn list_sequences(path: &Path) -> Result<()> {
+    let store = CommandStore::load(path)?;
+
+    if store.sequences.is_empty() {
+        println!("No sequences saved.");
+        return Ok(());
+    }
+    let mut names: Vec<&String> = store.sequences.keys().collect();
+    sort_commands(&mut names);
+    for name in names {
+        println!("{}", name.bold());
+        for (i, cmd) in store.sequences[name].iter().enumerate() {
+            println!("  [{}] {}", i + 1, mask_secrets(cmd).bright_green());
+        }
+    }
+    Ok(())
+}
+
+fn remove_sequence(path: &Path, name: String) -> Result<()> {
+    let mut store = CommandStore::load(path)?;
+
+    if store.sequences.remove(&name).is_none() {
+        info!("No sequence named '{}'", name);
+        return Err(NoMatch.into());
+    }
+    store.save_with_backup(path)?;
+    info!("Deleted sequence: {}", name);
+    Ok(())
+}
+
+fn copy_command(path: &Path, pattern: String) -> Result<()> {
+    let store = CommandStore::load(path)?;
+
+    let matching_commands = search_logic(pattern.clone(), &store, &SearchOptions::default())?;
+    if matching_commands.is_empty() {
+        info!("No commands found matching '{}'", pattern);
+    } else if let Some(cmd_to_copy) = select_command(&matching_commands, &store, "copy")? {
+        match copy_to_clipboard(&cmd_to_copy) {
+            Ok(()) => info!("Copied to clipboard: {}", cmd_to_copy),
+            Err(err) => {
+                eprintln!("{} {:#}", "Warning:".yellow(), err);
+                println!("{}", cmd_to_copy);
+            }
+        }
+    };
+    Ok(())
+}
+
+// On Linux the clipboard belongs to a running program, and keepc exits right away.
+// wl-copy and xclip stay in the background to serve it.
+#[cfg(all(feature = "clipboard", target_os = "linux"))]
+fn copy_to_clipboard(text: &str) -> Result<()> {
+    let (helper, args): (&str, &[&str]) = if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        ("wl-copy", &[])
+    } else {
+        ("xclip", &["-selection", "clipboard"])
+    };
+    if find_in_path(helper).is_none() {
+        return Err(anyhow::anyhow!("Copying needs {} installed, the clipboard is emptied when keepc exits otherwise", helper));
+    }
+    let mut child = Command::new(helper).args(args)
+    .stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::null())
+    .spawn().with_context(|| format!("Failed to run {}", helper))?;
+    child.stdin.take().context("Failed to copy to clipboard")?
+    .write_all(text.as_bytes()).context("Failed to copy to clipboard")?;
+    if !child.wait().with_context(|| format!("Failed to run {}", helper))?.success() {
+        return Err(anyhow::anyhow!("{} failed to copy to clipboard", helper));
+    }
+    Ok(())
+}
+
+#[cfg(all(feature = "clipboard", not(target_os = "linux")))]
+fn copy_to_clipboard(text: &str) -> Result<()> {
+    let mut clipboard = arboard::Clipboard::new().context("Clipboard is not available")?;
+    clipboard.set_text(text).context("Failed to copy to clipboard")?;
+    Ok(())
+}
+
+#[cfg(not(feature = "clipboard"))]
+fn copy_to_clipboard(_text: &str) -> Result<()> {
+    Err(anyhow::anyhow!("keepc was built without clipboard support"))
+}
+
+#[cfg(not(feature = "tui"))]
+fn browse(_path: &Path, _config: &Config) -> Result<()> {
+    Err(anyhow::anyhow!("keepc was built without the browser, rebuild it with `--features tui`"))
+}
+
+fn open_location(path: &Path, target: OpenTarget) -> Result<()> {
+    let location = match target {
+        OpenTarget::File => {
+            if !path.exists() {
+                return Err(anyhow::anyhow!("{} does not exist yet, save a command or run `keepc init` first", path.display()));
+            }
+            path.to_path_buf()
+        }
+        OpenTarget::Dir => {
+            let dir = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+            fs::create_dir_all(dir).context("Failed to create directory")?;
+            dir.to_path_buf()
+        }
+    };
+    // Printed first, so the path is there even when nothing can open it
+    println!("{}", location.display());
+
+    let opener = if cfg!(target_os = "windows") {
+        "explorer"
+    } else if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    if let Err(err) = Command::new(opener).arg(&location).stdout(Stdio::null()).stderr(Stdio::null()).spawn() {
+        eprintln!("{} could not run {}: {}", "Warning:".yellow(), opener, err);
+    }
+    Ok(())
+}
+
+fn clear_commands(path: &Path, backup: bool, remove_file: bool) -> Result<()> {
+    let mut store = CommandStore::load(path)?;
+
+    if store.commands.is_empty() && !(remove_file && path.exists()) {
+        println!("No commands saved.");
+        return Ok(());
+    }
+    println!("{}", format!("This deletes all {} saved commands in {}.", store.commands.len(), path.display()).red().bold());
+    if prompt("Type 'DELETE' to continue: ")? != "DELETE" {
+        println!("Aborted.");
+        return Ok(());
+    }
+
+    if backup {
+        let backup = with_suffix(path, &format!(".{}.bak", Local::now().format("%Y%m%d-%H%M%S")));
+        fs::copy(path, &backup).context("Failed to back up commands file")?;
+        info!("Backed up to {}", backup.display());
+    }
+    if remove_file {
+        fs::remove_file(path).context("Failed to delete commands file")?;
+        info!("Deleted {}", path.display());
+    } else {
+        store.commands.clear();
+        store.sequences.clear();
+        store.save_with_backup(path)?;
+        info!("Deleted all commands.");
+    }
+    Ok(())
+}
+
+fn undo_command(path: &Path) -> Result<()> {
+    let backup = with_suffix(path, ".bak");
+    if !backup.exists() {
+        return Err(anyhow::anyhow!("Nothing to undo: no backup found at {}", backup.display()));
+    }
+
+    // Swap the contents so running undo again redoes the change. Renaming the files
+    // would move a symlinked store's link instead of the file it points to.
+    let restored = fs::read(&backup).context("Failed to read backup")?;
+    let current = if path.exists() {
+        Some(fs::read(path).context("Failed to read commands file")?)
+    } else {
+        None
+    };
+    write_store_file(path, &restored).context("Failed to restore backup")?;
+    match current {
+        Some(current) => write_store_file(&backup, &current).context("Failed to keep the undone changes")?,
+        None => fs::remove_file(&backup).context("Failed to remove backup")?,
+    }
+    info!("Restored commands from backup.");
+    Ok(())
+}
+
+fn generate_completions(shell: Shell) -> Result<()> {
+    let mut command = Cli::command();
+    clap_complete::generate(shell, &mut command, "keepc", &mut std::io::stdout());
+    Ok(())
+}
+
+fn init_store(path: &Path, encrypt: bool, decrypt: bool) -> Result<()> {
+    let mut store = CommandStore::load(path)?;
+
+    if encrypt {
+        if store.encrypted {
+            info!("{} is already encrypted.", path.display());
+            return Ok(());
+        }
+        #[cfg(feature = "encryption")]
+        store_passphrase(true)?;
+        store.encrypted = true;
+        store.save(path)?;
+        // The backup for `undo` and the timestamped backups still hold the commands in
+        // plain text
+        let backup = with_suffix(path, ".bak");
+        if backup.exists() {
+            fs::remove_file(&backup).context("Failed to remove unencrypted backup")?;
+        }
+        for backup in list_backups(path)?.into_iter().filter(|backup| !is_encrypted_file(backup)) {
+            fs::remove_file(&backup).with_context(|| format!("Failed to remove unencrypted backup {}", backup.display()))?;
+        }
+        info!("Encrypted {}", path.display());
+    } else if decrypt {
+        if !store.encrypted {
+            info!("{} is not encrypted.", path.display());
+            return Ok(());
+        }
+        store.encrypted = false;
+        store.save(path)?;
+        info!("Decrypted {}", path.display());
+    } else if !path.exists() {
+        store.save(path)?;
+        info!("Created {}", path.display());
+    } else {
+        info!("{} already exists.", path.display());
+    }
+    Ok(())
+}
+
+fn print_version(verbose: bool) -> Result<()> {
+    println!("keepc {}", VERSION);
+    if verbose {
+        println!("rustc:  {}", env!("KEEPC_RUSTC_VERSION"));
+        println!("target: {}", env!("KEEPC_TARGET"));
+    }
+    Ok(())
+}
+
+// Print the resolved paths and settings without changing anything
+fn doctor(file: Option<&Path>, profile: Option<&str>) -> Result<()> {
+    let ok = |text: String| text.green();
+    let problem = |text: String| text.red();
+    println!("{} {}", "Version:       ".bold(), VERSION);
+
+    let config_dir = get_config_dir()?;
+    println!("{} {}", "Config dir:    ".bold(), config_dir.display());
+    let config_path = config_dir.join("config.toml");
+    let config = match Config::load(&config_path) {
+        Ok(config) if config_path.exists() => {
+            println!("{} {} {}", "Config file:   ".bold(), config_path.display(), ok("(valid)".to_string()));
+            config
+        }
+        Ok(config) => {
+            println!("{} {} {}", "Config file:   ".bold(), config_path.display(), "(not found, using defaults)".dimmed());
+            config
+        }
+        Err(err) => {
+            println!("{} {} {}", "Config file:   ".bold(), config_path.display(), problem(format!("({:#})", err)));
+            Config::default()
+        }
+    };
+
+    let path = get_commands_file(file, profile)?;
+    let status = if !path.exists() {
+        "(not found, nothing saved yet)".dimmed()
+    } else if is_encrypted_file(&path) {
+        ok("(encrypted)".to_string())
+    } else {
+        match fs::read(&path).map_err(anyhow::Error::from).and_then(|bytes| CommandStore::parse(&bytes, &path)) {
+            Ok(store) => ok(format!("(valid, {} commands)", store.commands.len())),
+            Err(err) => problem(format!("(invalid: {})", err)),
+        }
+    };
+    println!("{} {} {}", "Commands file: ".bold(), path.display(), status);
+
+    let editor = match find_editor(None, &config) {
+        Ok(editor) => editor.join(" "),
+        Err(err) => problem(err.to_string()).to_string(),
+    };
+    println!("{} {}", "Editor:        ".bold(), editor);
+    let shell = match find_shell(None) {
+        Ok((shell, shell_arg)) => format!("{} {}", shell, shell_arg),
+        Err(err) => problem(err.to_string()).to_string(),
+    };
+    println!("{} {}", "Shell:         ".bold(), shell);
+    Ok(())
+}
+
+fn list_profiles(active: Option<&str>) -> Result<()> {
+    let dir = get_config_dir()?;
+    let active = active.unwrap_or(DEFAULT_PROFILE);
+
+    let mut profiles = Vec::new();
+    if dir.exists() {
+        for entry in fs::read_dir(&dir).context("Failed to read config directory")? {
+            let path = entry.context("Failed to read config directory")?.path();
+            if path.extension().is_some_and(|ext| ext == "json" || ext == "yaml" || ext == "yml")
+                && let Some(name) = path.file_stem().and_then(|stem| stem.to_str())
+                && !profiles.iter().any(|profile| profile == name) {
+                profiles.push(name.to_string());
+            }
+        }
+    }
+    if profiles.is_empty() {
+        println!("No profiles found.");
+        return Ok(());
+    }
+
+    sort_commands(&mut profiles);
+    for profile in profiles {
+        if profile == active {
+            println!("* {}", profile.bright_green());
+        } else {
+            println!("  {}", profile);
+        }
+    }
+    Ok(())
+}
+
+// Number of single-character edits that turn one word into the other
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+// Find the subcommand a mistyped word was probably meant to be. Short words have to be
+// closer, so ordinary search words aren't taken for typos.
+fn suggest_subcommand(word: &str) -> Option<String> {
+    let word = word.to_lowercase();
+    let max_distance = if word.chars().count() < 7 { 1 } else { 2 };
+    Cli::command().get_subcommands()
+    .filter(|subcommand| !subcommand.is_hide_set())
+    .map(|subcommand| subcommand.get_name().to_string())
+    .map(|name| (edit_distance(&word, &name), name))
+    .filter(|(distance, _)| *distance <= max_distance)
+    .min()
+    .map(|(_, name)| name)
+}
+
+// Returned when a pattern matches no saved command, so `main` can exit with status 1
+// like grep does. The handler has already told the user.
+#[derive(Debug)]
+struct NoMatch;
+
+impl std::fmt::Display for NoMatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "No matching commands")
+    }
+}
+
+impl std::error::Error for NoMatch {}
+
+// Only color output for a terminal, unless the config file says otherwise, and never
+// when NO_COLOR is set
+fn configure_colors(config: &Config) {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    let color = !no_color && config.color.unwrap_or_else(|| std::io::stdout().is_terminal());
+    colored::control::set_override(color);
+}
+
+// Exit with 0 on success, 1 when nothing matched and 2 on any other error
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) if err.is::<NoMatch>() => ExitCode::from(1),
+        Err(err) => {
+            eprintln!("Error: {:?}", err);
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn run() -> Result<()> {
+    let cli = Cli::parse();
+    QUIET.store(cli.quiet, Ordering::Relaxed);
+    // Doctor reports problems with the config and commands files instead of failing on
+    // them, and must not move a corrupt file aside
+    if let Some(Commands::Doctor) = cli.command {
+        configure_colors(&Config::load(&get_config_dir()?.join("config.toml")).unwrap_or_default());
+        if cli.plain {
+            colored::control::set_override(false);
+        }
+        return doctor(cli.store_file.as_deref(), cli.profile.as_deref());
+    }
+    let config = Config::load(&get_config_dir()?.join("config.toml"))?;
+    configure_colors(&config);
+    if config.backups {
+        BACKUPS.store(config.max_backups.unwrap_or(10), Ordering::Relaxed);
+    }
+    let template = if cli.plain { None } else { cli.template.or_else(|| config.template.clone()) };
+    let output = OutputOptions { plain: cli.plain, reveal: cli.reveal, template };
+    if output.plain {
+        colored::control::set_override(false);
+    }
+    let path = get_commands_file(cli.store_file.as_deref(), cli.profile.as_deref())?;
+    if !cli.strict && cli.command.as_ref().is_some_and(Commands::loads_store) {
+        CommandStore::recover(&path)?;
+    }
+    match cli.command {
+        Some(Commands::New(args))
+        | Some(Commands::Add(args)) => new_command(&path, args),
+        Some(Commands::Last) => save_last_command(&path),
+        Some(Commands::List(args))
+        | Some(Commands::Ls(args)) => list_commands(&path, args, &output, &config),
+        Some(Commands::Grep(args))
+        | Some(Commands::Find(args))
+        | Some(Commands::Search(args)) => search_commands(&path, args, &output),
+        Some(Commands::Show { pattern }) => show_command(&path, pattern, &output),
+        Some(Commands::Remove(args))
+        | Some(Commands::Rm(args))
+        | Some(Commands::Delete(args)) => delete_command(&path, args),
+        Some(Commands::Rename { pattern }) => rename_command(&path, pattern),
+        Some(Commands::Duplicate { pattern })
+        | Some(Commands::Cp { pattern }) => duplicate_command(&path, pattern),
+        Some(Commands::Describe { pattern }) => describe_command(&path, pattern),
+        Some(Commands::Dedup { dry_run }) => dedup_commands(&path, dry_run),
+        Some(Commands::Annotate) => annotate_commands(&path),
+        Some(Commands::Tag { pattern, add, remove }) => tag_commands(&path, pattern, add, remove),
+        Some(Commands::Tags) => list_tags(&path),
+        Some(Commands::Pin { pattern }) => pin_command(&path, pattern, true),
+        Some(Commands::Unpin { pattern }) => pin_command(&path, pattern, false),
+        Some(Commands::Move { pattern, direction }) => move_command(&path, pattern, direction),
+        Some(Commands::Edit { editor }) => edit_commands(&path, editor, &config),
+        Some(Commands::Copy { pattern }) => copy_command(&path, pattern),
+        Some(Commands::Top) => top_commands(&path, &output),
+        Some(Commands::Recent { limit }) => recent_commands(&path, limit, &output),
+        Some(Commands::Count { by_tag }) => count_commands(&path, by_tag),
+        Some(Commands::Stats) => print_stats(&path, &output),
+        Some(Commands::Browse) => browse(&path, &config),
+        Some(Commands::Export { format }) => export_commands(&path, format),
+        Some(Commands::Import { file, dir, mode }) => import_commands(&path, file, dir, mode),
+        Some(Commands::Merge { file }) => merge_stores(&path, file),
+        Some(Commands::Clear { backup, remove_file }) => clear_commands(&path, backup, remove_file),
+        Some(Commands::Undo) => undo_command(&path),
+        Some(Commands::Completions { shell }) => generate_completions(shell),
+        Some(Commands::Init { encrypt, decrypt }) => init_store(&path, encrypt, decrypt),
+        Some(Commands::Version { verbose }) => print_version(verbose),
+        Some(Commands::Doctor) => doctor(cli.store_file.as_deref(), cli.profile.as_deref()),
+        Some(Commands::Open { target }) => open_location(&path, target),
+        Some(Commands::Profile(ProfileCommands::List)) => list_profiles(cli.profile.as_deref()),
+        Some(Commands::Seq(SeqCommands::New { name, commands, force })) => new_sequence(&path, name, commands, force),
+        Some(Commands::Seq(SeqCommands::Run { name, yes, keep_going, shell })) => {
+            run_sequence(&path, name, yes || config.confirm_run == Some(false), keep_going, shell)
+        }
+        Some(Commands::Seq(SeqCommands::List)) => list_sequences(&path),
+        Some(Commands::Seq(SeqCommands::Remove { name })) => remove_sequence(&path, name),
+        Some(Commands::Run(mut args))
+        | Some(Commands::Execute(mut args)) => {
+            args.yes |= config.confirm_run == Some(false);
+            execute_command(&path, args, &config)
+        }
+        Some(Commands::Pattern(args)) => {
+            let result = search_commands(&path, GrepArgs { pattern: args.join(" "), limit: None, options: cli.search }, &output);
+            // Only a search without results was probably a mistyped subcommand
+            if result.as_ref().is_err_and(|err| err.is::<NoMatch>())
+                && let Some(suggestion) = suggest_subcommand(&args[0]) {
+                eprintln!("{} '{}' is not a subcommand either. Did you mean '{}'?", "Note:".yellow(), args[0], suggestion);
+            }
+            result
+        }
+        None => {
+            Cli::parse_from(["keepc", "--help"]);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store_with(commands: &[(&str, &str)]) -> CommandStore {
+        let mut store = CommandStore::new();
+        for (command, description) in commands {
+            store.commands.insert(command.to_string(), CommandEntry { description: description.to_string(), ..Default::default() });
+        }
+        store
+    }
+
+    #[test]
+    fn case_sensitive_search_respects_case() {
+        let store = store_with(&[("aws s3 ls", "list buckets")]);
+        let options = SearchOptions { case_sensitive: true, ..Default::default() };
+        assert!(search_logic("AWS".to_string(), &store, &options).unwrap().is_empty());
+        assert_eq!(search_logic("aws".to_string(), &store, &options).unwrap(), vec!["aws s3 ls"]);
+        assert_eq!(search_logic("AWS".to_string(), &store, &SearchOptions::default()).unwrap(), vec!["aws s3 ls"]);
+    }
+
+    #[test]
+    fn search_requires_all_words_by_default() {
+        let store = store_with(&[("docker ps", "list containers"), ("kubectl get pods", "list pods")]);
+        let all = SearchOptions::default();
+        assert_eq!(search_logic("docker list".to_string(), &store, &all).unwrap(), vec!["docker ps"]);
+        assert!(search_logic("docker pods".to_string(), &store, &all).unwrap().is_empty());
+    }
+
+    #[test]
+    fn search_with_any_matches_one_of_the_words() {
+        let store = store_with(&[("docker ps", "list containers"), ("kubectl get pods", "list pods"), ("ls -la", "")]);
+        let any = SearchOptions { any: true, ..Default::default() };
+        assert_eq!(search_logic("docker pods".to_string(), &store, &any).unwrap(), vec!["docker ps", "kubectl get pods"]);
+        assert!(search_logic("helm".to_string(), &store, &any).unwrap().is_empty());
+    }
+
+    #[test]
+    fn empty_pattern_matches_everything_in_both_modes() {
+        let store = store_with(&[("docker ps", ""), ("ls -la", "")]);
+        let any = SearchOptions { any: true, ..Default::default() };
+        assert_eq!(search_logic(String::new(), &store, &SearchOptions::default()).unwrap().len(), 2);
+        assert_eq!(search_logic(String::new(), &store, &any).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn large_save_leaves_valid_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("commands.json");
+        let mut store = CommandStore::new();
+        for i in 0..5000 {
+            store.commands.insert(format!("echo {}", i), CommandEntry { description: "x".repeat(200), ..Default::default() });
+        }
+        store.save(&path).unwrap();
+        store.save(&path).unwrap();
+        let saved: serde_json::Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(saved["commands"].as_object().unwrap().len(), 5000);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn save_writes_through_symlink() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("dotfiles.json");
+        let link = dir.path().join("commands.json");
+        CommandStore::new().save(&target).unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        store_with(&[("ls", "list")]).save(&link).unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert!(CommandStore::load(&target).unwrap().commands.contains_key("ls"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn undo_swaps_through_symlink() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("dotfiles.json");
+        let link = dir.path().join("commands.json");
+        store_with(&[("ls", "list")]).save(&target).unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        CommandStore::new().save_with_backup(&link).unwrap();
+        undo_command(&link).unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert!(CommandStore::load(&target).unwrap().commands.contains_key("ls"));
+        let backup = with_suffix(&link, ".bak");
+        assert!(fs::symlink_metadata(&backup).unwrap().file_type().is_file());
+        assert!(CommandStore::load(&backup).unwrap().commands.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn recover_moves_the_target_of_a_symlink() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("dotfiles.json");
+        let link = dir.path().join("commands.json");
+        fs::write(&target, "{ not json").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        CommandStore::recover(&link).unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert!(!target.exists());
+        store_with(&[("ls", "list")]).save(&link).unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert!(CommandStore::load(&target).unwrap().commands.contains_key("ls"));
+    }
+
+    #[test]
+    fn posix_shell_expands_env_values() {
+        let env = HashMap::from([
+            ("PATH".to_string(), "$HOME/bin:$PATH".to_string()),
+            ("GREETING".to_string(), r#"say "hi" \o/"#.to_string()),
+        ]);
+        assert_eq!(shell_line("-c", "make", &env), r#"export GREETING="say \"hi\" \\o/"; export PATH="$HOME/bin:$PATH"; make"#);
+        assert_eq!(shell_line("/C", "make", &env), "make");
+    }
+
+    #[test]
+    fn env_names_must_be_valid() {
+        assert_eq!(parse_env_var("API_URL=$HOST/api"), Ok(("API_URL".to_string(), "$HOST/api".to_string())));
+        assert!(parse_env_var("1X=a").is_err());
+        assert!(parse_env_var("A B=a").is_err());
+    }
+
+    #[test]
+    fn dangerous_commands_are_detected() {
+        let cases = [
+            ("rm -rf /", true),
+            ("rm -fr build", true),
+            ("rm -r -f /", true),
+            ("rm -f -r /", true),
+            ("rm -R -f /", true),
+            ("rm -r --force /", true),
+            ("rm --recursive -f /", true),
+            ("rm --force --recursive /", true),
+            ("sudo rm -v -r -f /tmp/x", true),
+            ("cd /tmp && /bin/rm -r -f x", true),
+            ("dd if=/dev/zero of=/dev/sda", true),
+            ("mkfs.ext4 /dev/sdb1", true),
+            ("rm -r build", false),
+            ("rm -f file.txt", false),
+            ("rm -f -- -r", false),
+            ("ls -rf", false),
+            ("git rm -r --cached . && echo -f", false),
+        ];
+        for (cmd, dangerous) in cases {
+            assert_eq!(is_dangerous(cmd, &CommandEntry::default()), dangerous, "{}", cmd);
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn imported_entries_lose_control_characters_everywhere() {
+        let mut entry = CommandEntry {
+            tags: vec!["ops\x1b[2J".to_string()],
+            aliases: vec!["dep\x07loy".to_string()],
+            cwd: Some("~/src\x1b]0;x\x07".to_string()),
+            env: HashMap::from([("STAGE".to_string(), "prod\x1b[31m".to_string())]),
+            last_output: Some("done\x1b[0m\nok".to_string()),
+            ..Default::default()
+        };
+        assert!(sanitize_entry(&mut entry));
+        assert_eq!(entry.tags, ["ops[2J"]);
+        assert_eq!(entry.aliases, ["deploy"]);
+        assert_eq!(entry.cwd.as_deref(), Some("~/src]0;x"));
+        assert_eq!(entry.env["STAGE"], "prod[31m");
+        assert_eq!(entry.last_output.as_deref(), Some("done[0m\nok"));
+        assert!(!sanitize_entry(&mut entry));
+    }
+
+    #[test]
+    fn direct_mode_expands_entry_env_first() {
+        let env = HashMap::from([("KEEPC_TEST_API".to_string(), "https://example.com".to_string())]);
+        assert_eq!(split_simple_command("curl $KEEPC_TEST_API", &env), Some(vec!["curl".to_string(), "https://example.com".to_string()]));
+        assert_eq!(split_simple_command("curl ${KEEPC_TEST_API}/x", &env), Some(vec!["curl".to_string(), "https://example.com/x".to_string()]));
+    }
+
+    #[test]
+    fn direct_mode_leaves_single_quotes_to_the_shell() {
+        let env = HashMap::from([("KEEPC_TEST_API".to_string(), "x".to_string())]);
+        assert_eq!(expand_variables("echo '$KEEPC_TEST_API'", &env), "echo '$KEEPC_TEST_API'");
+        assert_eq!(expand_variables("echo \"$KEEPC_TEST_API\"", &env), "echo \"x\"");
+        assert_eq!(split_simple_command("echo '$KEEPC_TEST_API'", &env), None);
+    }
+
+    #[test]
+    fn backups_of_other_profiles_are_left_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let backups = dir.path().join("backups");
+        fs::create_dir_all(&backups).unwrap();
+        for name in ["work-20260101-120000-000.json", "work-2-20260101-120000-000.json", "work-notes.json"] {
+            fs::write(backups.join(name), "{}").unwrap();
+        }
+        let found = list_backups(&dir.path().join("work.json")).unwrap();
+        assert_eq!(found, vec![backups.join("work-20260101-120000-000.json")]);
+    }
+
+    #[test]
+    fn renamed_commands_stay_in_their_sequences() {
+        let mut store = store_with(&[("make", ""), ("make test", "")]);
+        store.sequences.insert("ci".to_string(), vec!["make".to_string(), "make test".to_string()]);
+        store.rename_in_sequences("make test", "cargo test");
+        assert_eq!(store.sequences["ci"], ["make", "cargo test"]);
+    }
+
+    #[test]
+    fn deleted_commands_are_dropped_from_sequences() {
+        let mut store = store_with(&[("make", "")]);
+        store.sequences.insert("ci".to_string(), vec!["make".to_string(), "make test".to_string()]);
+        store.sequences.insert("test".to_string(), vec!["make test".to_string()]);
+        store.sequences.insert("build".to_string(), vec!["make".to_string()]);
+        assert_eq!(store.prune_sequences(), ["ci", "test"]);
+        assert_eq!(store.sequences["ci"], ["make"]);
+        assert!(!store.sequences.contains_key("test"));
+        assert_eq!(store.sequences["build"], ["make"]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn snippets_run_with_their_shebang() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("hello.sh");
+        fs::write(&file, "#!/usr/bin/env python3\n# Say hello\nprint('hello')\n").unwrap();
+        let quoted = shell_quote(&file.canonicalize().unwrap().to_string_lossy());
+        let store = read_snippets(dir.path()).unwrap();
+        assert_eq!(store.commands.keys().collect::<Vec<_>>(), [&format!("/usr/bin/env python3 {}", quoted)]);
+
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o755)).unwrap();
+        let store = read_snippets(dir.path()).unwrap();
+        assert_eq!(store.commands.keys().collect::<Vec<_>>(), [&quoted]);
+    }
+
+    #[test]
+    fn subcommand_typed_in_capitals_is_suggested() {
+        assert_eq!(suggest_subcommand("LIST").as_deref(), Some("list"));
+        assert_eq!(suggest_subcommand("lisst").as_deref(), Some("list"));
+    }
+
+    #[test]
+    fn unique_key_counts_past_taken_copies() {
+        let store = store_with(&[("make", ""), ("make # imported", "")]);
+        assert_eq!(unique_key(&store, "make", "imported"), "make # imported 2");
+        assert_eq!(unique_key(&store, "make", "theirs"), "make # theirs");
+    }
+
+    // Write a command and description the way `edit` does and parse the line back
+    fn edit_round_trip(command: &str, description: &str) -> Option<(String, String)> {
+        parse_edit_line(&format!("{}:::{}", escape_edit_value(command), escape_edit_value(description)))
+    }
+
+    #[test]
+    fn edit_line_keeps_separator_in_command() {
+        assert_eq!(edit_round_trip("a:::b", "desc"), Some(("a:::b".to_string(), "desc".to_string())));
+    }
+
+    #[test]
+    fn edit_line_keeps_trailing_colon() {
+        assert_eq!(edit_round_trip("echo a:", "ends with:"), Some(("echo a:".to_string(), "ends with:".to_string())));
+    }
+
+    #[test]
+    fn edit_line_keeps_description_starting_with_colons() {
+        assert_eq!(edit_round_trip("ls", "::double"), Some(("ls".to_string(), "::double".to_string())));
+    }
+
+    #[test]
+    fn edit_line_keeps_newlines_and_backslashes() {
+        let command = "printf 'a\\nb' \\\\ c";
+        let description = "first line\nsecond \\ line";
+        assert_eq!(edit_round_trip(command, description), Some((command.to_string(), description.to_string())));
+    }
+}